@@ -0,0 +1,81 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use csvlint::structural::{field_counts, scan_line_breaks};
+use csvlint::validate_slice;
+use std::hint::black_box;
+
+/// Files shipped in `test_data`, with their delimiters
+const TEST_FILES: &[(&str, u8)] = &[
+    ("perfect.csv", b','),
+    ("perfect_tab.csv", b'\t'),
+    ("perfect_pipe.csv", b'|'),
+    ("mult_long_columns.csv", b','),
+];
+
+/// Generates a CSV document of roughly `size` bytes with quoted fields and embedded newlines
+fn generate(size: usize) -> Vec<u8> {
+    let mut data = String::from("id,name,email,notes,amount\r\n");
+    let mut i = 0;
+    while data.len() < size {
+        if i % 10 == 0 {
+            data.push_str(&format!(
+                "{i},\"Doe, Jane\",jane{i}@example.com,\"said \"\"hi\"\"\r\nthen left\",{i}.50\r\n"
+            ));
+        } else {
+            data.push_str(&format!(
+                "{i},John Smith,john{i}@example.com,plain notes here,{i}.25\r\n"
+            ));
+        }
+        i += 1;
+    }
+    data.into_bytes()
+}
+
+fn bench_test_data(c: &mut Criterion) {
+    let mut group = c.benchmark_group("test_data");
+    for &(file, delimiter) in TEST_FILES {
+        let content = std::fs::read(format!("test_data/{file}")).unwrap();
+        group.throughput(Throughput::Bytes(content.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("validate", file),
+            &content,
+            |b, content| {
+                b.iter(|| validate_slice(black_box(content), delimiter, false, true).unwrap())
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_generated(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generated");
+    for size in [1 << 20, 16 << 20] {
+        let content = generate(size);
+        group.throughput(Throughput::Bytes(content.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("validate", size),
+            &content,
+            |b, content| b.iter(|| validate_slice(black_box(content), b',', false, true).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("scan_line_breaks", size),
+            &content,
+            |b, content| {
+                b.iter(|| {
+                    let mut breaks = 0;
+                    scan_line_breaks(black_box(content), |_, _| breaks += 1);
+                    breaks
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("field_counts", size),
+            &content,
+            |b, content| b.iter(|| field_counts(black_box(content), b',', true, None)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_test_data, bench_generated);
+criterion_main!(benches);
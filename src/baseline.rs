@@ -0,0 +1,304 @@
+//! Baselines of known errors
+//!
+//! A baseline is a JSON file recording the errors a file had when it was
+//! written. Validating against it reports only errors that are not in it,
+//! and lists its entries that no longer occur so that it can be shrunk.
+//!
+//! Entries are keyed by file, rule and a fingerprint of the content the
+//! error is about, rather than by record number, so that inserting or
+//! removing records doesn't invalidate the baseline:
+//!
+//! - for errors about a record, its field values, plus the column for
+//!   rules about one field
+//! - for errors about a line, such as line endings, the text of the line
+//! - for records that couldn't be parsed, their raw bytes
+
+use crate::{CsvError, ValidationOptions, skip, tokenizer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Version of the baseline file format
+const VERSION: u32 = 1;
+
+/// Errors reading or writing a baseline
+#[derive(Debug, Error)]
+pub enum BaselineError {
+    #[error("cannot read '{0}': {1}")]
+    Read(PathBuf, io::Error),
+    #[error("cannot write '{0}': {1}")]
+    Write(PathBuf, io::Error),
+    #[error("invalid baseline '{0}': {1}")]
+    Parse(PathBuf, serde_json::Error),
+    #[error("baseline '{0}' has version {1}, expected {VERSION}")]
+    Version(PathBuf, u32),
+}
+
+/// Known errors, by file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    version: u32,
+    pub entries: Vec<BaselineEntry>,
+}
+
+/// One known error
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    /// The file, as given on the command line
+    pub file: String,
+    /// Name of the error kind, as returned by [`crate::CsvErrorKind::name`]
+    pub rule: String,
+    /// Hash of the content the error is about
+    pub fingerprint: String,
+    /// Record (or line) number when the baseline was written, for reference only
+    pub record: usize,
+    /// Whether `record` is a line number
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub line: bool,
+}
+
+impl fmt::Display for BaselineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = if self.line { "line" } else { "record" };
+        write!(f, "{} on {} {}", self.rule, unit, self.record)
+    }
+}
+
+/// The errors of a file, split by a baseline
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BaselineOutcome {
+    /// Errors not in the baseline
+    pub errors: Vec<CsvError>,
+    /// Number of errors suppressed because they are in the baseline
+    pub suppressed: usize,
+    /// Entries of the baseline for this file that no longer occur
+    pub fixed: Vec<BaselineEntry>,
+}
+
+impl Default for Baseline {
+    fn default() -> Self {
+        Baseline {
+            version: VERSION,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self, BaselineError> {
+        let text = fs::read_to_string(path).map_err(|e| BaselineError::Read(path.into(), e))?;
+        let baseline: Baseline =
+            serde_json::from_str(&text).map_err(|e| BaselineError::Parse(path.into(), e))?;
+        if baseline.version != VERSION {
+            return Err(BaselineError::Version(path.into(), baseline.version));
+        }
+        Ok(baseline)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BaselineError> {
+        let json = serde_json::to_string_pretty(self).expect("baselines serialize to JSON");
+        fs::write(path, json + "\n").map_err(|e| BaselineError::Write(path.into(), e))
+    }
+
+    /// Records the errors of `file`, whose content is `content`
+    pub fn add(
+        &mut self,
+        file: &str,
+        errors: &[CsvError],
+        content: &[u8],
+        options: &ValidationOptions,
+    ) {
+        let mut fingerprints = Fingerprints::new(content, options);
+        self.entries
+            .extend(errors.iter().map(|error| BaselineEntry {
+                file: file.to_string(),
+                rule: error.error.name().to_string(),
+                fingerprint: fingerprints.of(error),
+                record: error.record_num,
+                line: error.error.is_line_based(),
+            }));
+    }
+
+    /// Removes the errors of `file` that are in the baseline
+    pub fn apply(
+        &self,
+        file: &str,
+        errors: Vec<CsvError>,
+        content: &[u8],
+        options: &ValidationOptions,
+    ) -> BaselineOutcome {
+        let mut known: HashMap<(String, String), Vec<&BaselineEntry>> = HashMap::new();
+        for entry in self.entries.iter().filter(|entry| entry.file == file) {
+            known
+                .entry((entry.rule.clone(), entry.fingerprint.clone()))
+                .or_default()
+                .push(entry);
+        }
+
+        let mut fingerprints = Fingerprints::new(content, options);
+        let mut outcome = BaselineOutcome::default();
+        for error in errors {
+            let key = (error.error.name().to_string(), fingerprints.of(&error));
+            match known.get_mut(&key) {
+                Some(entries) if !entries.is_empty() => {
+                    entries.pop();
+                    outcome.suppressed += 1;
+                }
+                _ => outcome.errors.push(error),
+            }
+        }
+
+        outcome.fixed = known.into_values().flatten().cloned().collect();
+        outcome.fixed.sort_by_key(|entry| entry.record);
+        outcome
+    }
+}
+
+/// Computes fingerprints of the errors in some content
+struct Fingerprints<'a> {
+    content: &'a [u8],
+    options: &'a ValidationOptions,
+    /// Byte ranges of the records, the header first; found when first needed
+    records: Option<Vec<Range<usize>>>,
+    /// Byte ranges of the lines; found when first needed
+    lines: Option<Vec<Range<usize>>>,
+}
+
+impl<'a> Fingerprints<'a> {
+    fn new(content: &'a [u8], options: &'a ValidationOptions) -> Self {
+        Fingerprints {
+            content,
+            options,
+            records: None,
+            lines: None,
+        }
+    }
+
+    fn of(&mut self, error: &CsvError) -> String {
+        let mut hasher = Fnv::new();
+        if error.error.is_line_based() {
+            hasher.write(self.line(error.record_num));
+        } else if let Some(record) = &error.record {
+            for field in record {
+                // The length keeps ("a,b", "c") apart from ("a", "b,c")
+                hasher.write(&field.len().to_le_bytes());
+                hasher.write(field.as_bytes());
+            }
+            if let Some(column) = error.error.column() {
+                hasher.write(&column.index.to_le_bytes());
+            }
+        } else {
+            hasher.write(self.raw_record(error.record_num));
+        }
+        format!("{:016x}", hasher.0)
+    }
+
+    /// A line as numbered by validation, without its terminator
+    fn line(&mut self, line_num: usize) -> &'a [u8] {
+        let content = self.content;
+        let lines = self.lines.get_or_insert_with(|| skip::lines(content));
+        line_num
+            .checked_sub(1)
+            .and_then(|i| lines.get(i))
+            .map_or(&[][..], |line| &content[line.clone()])
+    }
+
+    /// The bytes of a record, counting the header as record 0
+    fn raw_record(&mut self, record_num: usize) -> &'a [u8] {
+        let options = self.options;
+        let content = skip::data_slice(self.content, options);
+        let records = self.records.get_or_insert_with(|| {
+            let mut records = Vec::new();
+            tokenizer::records(content, options, |span| records.push(span));
+            records
+        });
+        records
+            .get(record_num)
+            .map_or(&[][..], |span| &content[span.clone()])
+    }
+}
+
+/// 64-bit FNV-1a, which unlike the standard library's hasher is stable
+/// across Rust releases, as fingerprints in saved baselines must be
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FieldScope, validate_slice_with_options};
+
+    fn errors(content: &[u8], options: &ValidationOptions) -> Vec<CsvError> {
+        validate_slice_with_options(content, options)
+            .unwrap()
+            .errors
+    }
+
+    #[test]
+    fn test_baseline() {
+        let options = ValidationOptions {
+            surrounding_whitespace: FieldScope::All,
+            ..Default::default()
+        };
+        let old = b"a,b\n1\n2, x\n3\r\n";
+        let mut baseline = Baseline::default();
+        baseline.add("data.csv", &errors(old, &options), old, &options);
+        assert_eq!(baseline.entries.len(), 4);
+
+        // A record inserted at the top shifts the known errors, which stay
+        // suppressed; the whitespace error is fixed and a new one appears
+        let new = b"a,b\n0,0\n1\n2,x \n3\r\n";
+        let outcome = baseline.apply("data.csv", errors(new, &options), new, &options);
+        assert_eq!(outcome.suppressed, 3);
+        let remaining: Vec<String> = outcome.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            remaining,
+            vec!["Record #3 has error: trailing whitespace in column 2 (\"b\")"]
+        );
+        assert_eq!(outcome.fixed.len(), 1);
+        assert_eq!(
+            outcome.fixed[0].to_string(),
+            "LeadingWhitespace on record 2"
+        );
+
+        // Entries of other files don't apply
+        let outcome = baseline.apply("other.csv", errors(new, &options), new, &options);
+        assert_eq!(outcome.suppressed, 0);
+        assert!(outcome.fixed.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("baseline.json");
+        let options = ValidationOptions::default();
+        let content = b"a,b\n1\n";
+        let mut baseline = Baseline::default();
+        baseline.add("data.csv", &errors(content, &options), content, &options);
+        baseline.save(&path).unwrap();
+        assert_eq!(Baseline::load(&path).unwrap(), baseline);
+
+        fs::write(&path, r#"{"version": 2, "entries": []}"#).unwrap();
+        assert!(matches!(
+            Baseline::load(&path),
+            Err(BaselineError::Version(_, 2))
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+//! Final newline and blank line rules
+//!
+//! The csv crate silently skips empty lines, so they never reach the record
+//! checks. These rules look at the physical lines instead, ignoring line
+//! breaks inside quoted fields. Lines are numbered from 1 and CR, LF and CRLF
+//! each end one line.
+
+use crate::collector::ErrorCollector;
+use crate::line_endings::count_line_breaks;
+use crate::{CsvError, CsvErrorKind, Sample, ValidationOptions, tokenizer};
+
+/// Whether the last line of a file must end with a line break
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FinalNewline {
+    /// Either is accepted, as permitted by RFC 4180
+    #[default]
+    Any,
+    /// The last line must end with a line break
+    Require,
+    /// The last line must not end with a line break
+    Forbid,
+}
+
+/// Validates the final newline and blank line rules enabled in `options`
+///
+/// `first_line` is the line number of the first line in `content`.
+pub(crate) fn validate_blank_lines(
+    content: &[u8],
+    first_line: usize,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) {
+    let enabled = options.final_newline != FinalNewline::Any
+        || options.blank_lines
+        || options.trailing_blank_lines;
+    if !enabled || content.is_empty() {
+        return;
+    }
+
+    // A row sample only covers the start of the file, so its end says nothing about the file's
+    let whole_file = !matches!(options.sample, Sample::FirstRows(_));
+
+    let mut line_num = first_line;
+    let mut line_start = 0;
+    let mut blank_lines: Vec<usize> = Vec::new();
+    let push = |line_num: usize, error: CsvErrorKind, errors: &mut ErrorCollector| {
+        if options.sample.includes(line_num) {
+            errors.push(CsvError {
+                record: None,
+                record_num: line_num,
+                error,
+            });
+        }
+    };
+
+    tokenizer::line_breaks(content, options, |offset, byte| {
+        if byte == b'\n' && offset > 0 && content[offset - 1] == b'\r' {
+            // The LF of a CRLF; the line already ended at the CR
+            line_start = offset + 1;
+            return;
+        }
+
+        if offset == line_start {
+            blank_lines.push(line_num);
+        } else {
+            line_num += count_line_breaks(&content[line_start..offset]);
+            // Blank lines followed by content are in the middle of the file
+            if options.blank_lines {
+                for &blank in &blank_lines {
+                    push(blank, CsvErrorKind::BlankLine, errors);
+                }
+            }
+            blank_lines.clear();
+        }
+        line_num += 1;
+        line_start = offset + 1;
+    });
+
+    let ends_with_newline = line_start == content.len();
+    if !ends_with_newline {
+        line_num += count_line_breaks(&content[line_start..]);
+        // The unterminated last line has content, so every blank line before it is in the middle
+        if options.blank_lines {
+            for &blank in &blank_lines {
+                push(blank, CsvErrorKind::BlankLine, errors);
+            }
+        }
+        blank_lines.clear();
+    }
+
+    if !whole_file {
+        return;
+    }
+
+    if options.trailing_blank_lines {
+        for &blank in &blank_lines {
+            push(blank, CsvErrorKind::TrailingBlankLine, errors);
+        }
+    }
+
+    match options.final_newline {
+        FinalNewline::Require if !ends_with_newline => {
+            push(line_num, CsvErrorKind::MissingFinalNewline, errors);
+        }
+        FinalNewline::Forbid if ends_with_newline => {
+            push(line_num - 1, CsvErrorKind::UnexpectedFinalNewline, errors);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorLimits;
+
+    fn check(content: &str, options: &ValidationOptions) -> Vec<(usize, &'static str)> {
+        let limits = ErrorLimits::default();
+        let mut errors = ErrorCollector::new(&limits);
+        validate_blank_lines(content.as_bytes(), 1, options, &mut errors);
+        let (errors, _) = errors.into_parts();
+        errors
+            .iter()
+            .map(|e| (e.record_num, e.error.name()))
+            .collect()
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let options = ValidationOptions {
+            blank_lines: true,
+            trailing_blank_lines: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            check("\na,b\r\n\r\n1,\"x\n\ny\"\n\n2,3\n\n\n", &options),
+            vec![
+                (1, "BlankLine"),
+                (3, "BlankLine"),
+                (7, "BlankLine"),
+                (9, "TrailingBlankLine"),
+                (10, "TrailingBlankLine"),
+            ]
+        );
+        assert_eq!(check("a,b\n\n1,2", &options), vec![(2, "BlankLine")]);
+        assert!(check("a,b\n1,2\n", &options).is_empty());
+    }
+
+    #[test]
+    fn test_final_newline() {
+        let require = ValidationOptions {
+            final_newline: FinalNewline::Require,
+            ..Default::default()
+        };
+        assert_eq!(check("a\r\nb", &require), vec![(2, "MissingFinalNewline")]);
+        assert!(check("a\r\nb\r\n", &require).is_empty());
+        // A line break inside a quoted field doesn't end the file
+        assert_eq!(
+            check("a\n\"b\n\"", &require),
+            vec![(3, "MissingFinalNewline")]
+        );
+
+        let forbid = ValidationOptions {
+            final_newline: FinalNewline::Forbid,
+            ..Default::default()
+        };
+        assert_eq!(
+            check("a\r\nb\r\n", &forbid),
+            vec![(2, "UnexpectedFinalNewline")]
+        );
+        assert!(check("a\r\nb", &forbid).is_empty());
+    }
+}
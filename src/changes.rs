@@ -0,0 +1,150 @@
+//! Restricting errors to the records changed since an older version
+//!
+//! Records are compared by their raw bytes, including their line break, so
+//! a record counts as unchanged when an identical record exists in the old
+//! version, wherever it was. Errors about the file as a whole are always
+//! kept (see [`crate::CsvErrorKind::is_file_level`]).
+
+use crate::{CsvError, ValidationOptions, skip, tokenizer};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The records of a file, and which of them are new or changed
+#[derive(Debug, Clone)]
+pub struct RecordChanges {
+    /// Byte ranges of the records including their line break, the header first
+    records: Vec<Range<usize>>,
+    changed: Vec<bool>,
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl RecordChanges {
+    /// Compares `new` against `old`; without an old version, every record is new
+    pub fn new(old: Option<&[u8]>, new: &[u8], options: &ValidationOptions) -> Self {
+        let mut unchanged: HashMap<&[u8], usize> = HashMap::new();
+        if let Some(old) = old {
+            for span in records(old, options) {
+                *unchanged.entry(&old[span]).or_default() += 1;
+            }
+        }
+
+        let records = records(new, options);
+        let changed = records
+            .iter()
+            .map(|span| match unchanged.get_mut(&new[span.clone()]) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            })
+            .collect();
+
+        let line_starts = skip::lines(new).iter().map(|line| line.start).collect();
+
+        RecordChanges {
+            records,
+            changed,
+            line_starts,
+        }
+    }
+
+    /// Number of data records that are new or changed
+    pub fn changed_records(&self) -> usize {
+        self.changed
+            .iter()
+            .skip(1)
+            .filter(|&&changed| changed)
+            .count()
+    }
+
+    /// Whether `error` is about the file as a whole or a new or changed record
+    pub fn is_relevant(&self, error: &CsvError) -> bool {
+        if error.error.is_file_level() {
+            return true;
+        }
+        let record = if error.error.is_line_based() {
+            // Line ending errors belong to the record the line is part of
+            let Some(&start) = error
+                .record_num
+                .checked_sub(1)
+                .and_then(|i| self.line_starts.get(i))
+            else {
+                return true;
+            };
+            let record = self.records.partition_point(|span| span.start <= start);
+            match record.checked_sub(1) {
+                Some(record) if start < self.records[record].end => record,
+                // Blank lines aren't part of any record
+                _ => return true,
+            }
+        } else {
+            error.record_num
+        };
+        // Errors past the last record, such as on a truncated record, are kept
+        self.changed.get(record).copied().unwrap_or(true)
+    }
+
+    /// Keeps the errors that are about the file or a new or changed record
+    pub fn filter(&self, errors: Vec<CsvError>) -> Vec<CsvError> {
+        errors
+            .into_iter()
+            .filter(|error| self.is_relevant(error))
+            .collect()
+    }
+}
+
+/// Byte ranges of the records of `content`, each with its line break
+fn records(content: &[u8], options: &ValidationOptions) -> Vec<Range<usize>> {
+    let data = skip::data(content, options).range;
+    let offset = data.start;
+    let content = &content[data];
+    let mut records = Vec::new();
+    tokenizer::records(content, options, |span| {
+        let rest = &content[span.end..];
+        let terminator = if rest.starts_with(b"\r\n") {
+            2
+        } else {
+            usize::from(rest.starts_with(b"\n") || rest.starts_with(b"\r"))
+        };
+        records.push(offset + span.start..offset + span.end + terminator);
+    });
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate_slice_with_options;
+
+    #[test]
+    fn test_changed_records() {
+        let options = ValidationOptions::default();
+        let old = b"a,b\n1,2\n3\n4,5\nx\n";
+        // A record is inserted at the top and one appended, "3" changes its
+        // line ending, and the bad record "x" moves but is unchanged
+        let new = b"a,b\n6\n1,2\n3\r\n4,5\nx\n7,8,9\n";
+        let changes = RecordChanges::new(Some(old), new, &options);
+        assert_eq!(changes.changed_records(), 3);
+
+        let errors = validate_slice_with_options(new, &options).unwrap().errors;
+        let reported: Vec<String> = changes
+            .filter(errors)
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                "Line #4 has error: mixed line endings: found CRLF, file predominantly uses LF",
+                "Record #1 has error: wrong number of fields",
+                "Record #3 has error: wrong number of fields",
+                "Record #6 has error: wrong number of fields",
+            ]
+        );
+
+        let changes = RecordChanges::new(None, new, &options);
+        assert_eq!(changes.changed_records(), 6);
+    }
+}
@@ -0,0 +1,231 @@
+//! Control character, NUL byte and invisible Unicode rules
+//!
+//! NUL bytes, C0/C1 control characters, zero-width characters, bidi controls
+//! and lone surrogates all parse as ordinary text, but corrupt warehouse
+//! loads and can hide content from reviewers. Characters in
+//! [`ValidationOptions::allowed_characters`] are never reported.
+
+use crate::collector::ErrorCollector;
+use crate::{Column, CsvError, CsvErrorKind, ValidationOptions, csv_reader_builder};
+use csv::StringRecord;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// A reported character and where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Character {
+    /// The code point; for a lone surrogate, the surrogate's value
+    pub code_point: u32,
+    /// The column of the field
+    pub column: Column,
+    /// Byte offset of the character within the field value
+    pub offset: usize,
+}
+
+impl fmt::Display for Character {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "U+{:04X} in {} at byte {}",
+            self.code_point, self.column, self.offset
+        )
+    }
+}
+
+/// Characters allowed by default: tab, LF and CR, which are common inside fields
+pub fn default_allowed_characters() -> Vec<RangeInclusive<u32>> {
+    vec![0x09..=0x0a, 0x0d..=0x0d]
+}
+
+/// The error kind reported for `c`, if it is one the rules look for
+fn classify(c: char) -> Option<fn(Character) -> CsvErrorKind> {
+    match c {
+        '\0' => Some(CsvErrorKind::NulByte),
+        '\u{1}'..='\u{1f}' | '\u{7f}'..='\u{9f}' => Some(CsvErrorKind::ControlCharacter),
+        '\u{61c}' | '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => {
+            Some(CsvErrorKind::BidiControl)
+        }
+        '\u{ad}' | '\u{180e}' | '\u{200b}'..='\u{200d}' | '\u{2060}'..='\u{2064}' | '\u{feff}' => {
+            Some(CsvErrorKind::InvisibleCharacter)
+        }
+        _ => None,
+    }
+}
+
+fn is_allowed(options: &ValidationOptions, code_point: u32) -> bool {
+    options
+        .allowed_characters
+        .iter()
+        .any(|range| range.contains(&code_point))
+}
+
+/// Flags the control and invisible characters in the fields of one record
+pub(crate) fn validate_characters(
+    record: &StringRecord,
+    header: Option<&StringRecord>,
+    record_num: usize,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) {
+    for (i, field) in record.iter().enumerate() {
+        // Everything the rules look for is outside printable ASCII
+        if field.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+            continue;
+        }
+
+        for (offset, c) in field.char_indices() {
+            if let Some(kind) = classify(c)
+                && !is_allowed(options, c as u32)
+            {
+                errors.push(CsvError {
+                    record: Some(record.iter().map(|s| s.to_string()).collect()),
+                    record_num,
+                    error: kind(Character {
+                        code_point: c as u32,
+                        column: Column::new(i, header),
+                        offset,
+                    }),
+                });
+            }
+        }
+    }
+}
+
+/// Reports the lone surrogates in a record that failed to decode as UTF-8
+///
+/// Surrogates encoded on their own (as produced by WTF-8 or CESU-8 encoders)
+/// are invalid UTF-8. Returns `false`, reporting nothing, when the record is
+/// invalid for any other reason, so that the caller reports a UTF-8 error.
+pub(crate) fn validate_surrogates(
+    raw: &[u8],
+    header: Option<&StringRecord>,
+    record_num: usize,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) -> bool {
+    let mut csv_reader = csv_reader_builder(options).from_reader(raw);
+    let mut record = csv::ByteRecord::new();
+    if !matches!(csv_reader.read_byte_record(&mut record), Ok(true)) {
+        return false;
+    }
+    validate_record_surrogates(&record, header, record_num, options, errors)
+}
+
+/// Reports the lone surrogates in the fields of `record`, see [`validate_surrogates`]
+pub(crate) fn validate_record_surrogates(
+    record: &csv::ByteRecord,
+    header: Option<&StringRecord>,
+    record_num: usize,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) -> bool {
+    let mut surrogates = Vec::new();
+    for (i, field) in record.iter().enumerate() {
+        let mut rest = field;
+        let mut offset = 0;
+        while let Err(e) = std::str::from_utf8(rest) {
+            let at = e.valid_up_to();
+            match rest[at..] {
+                [0xed, b1 @ 0xa0..=0xbf, b2 @ 0x80..=0xbf, ..] => {
+                    let code_point = 0xd000 | ((b1 as u32 & 0x3f) << 6) | (b2 as u32 & 0x3f);
+                    surrogates.push((i, offset + at, code_point));
+                    rest = &rest[at + 3..];
+                    offset += at + 3;
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    let record: Vec<String> = record
+        .iter()
+        .map(|field| String::from_utf8_lossy(field).into_owned())
+        .collect();
+    for (i, offset, code_point) in surrogates {
+        if !is_allowed(options, code_point) {
+            errors.push(CsvError {
+                record: Some(record.clone()),
+                record_num,
+                error: CsvErrorKind::LoneSurrogate(Character {
+                    code_point,
+                    column: Column::new(i, header),
+                    offset,
+                }),
+            });
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorLimits;
+
+    #[test]
+    fn test_validate_characters() {
+        let record =
+            StringRecord::from(vec!["a\0b", "tab\tok", "zero\u{200b}width", "\u{202e}evil"]);
+        let options = ValidationOptions {
+            characters: true,
+            ..Default::default()
+        };
+        let limits = ErrorLimits::default();
+        let mut errors = ErrorCollector::new(&limits);
+        validate_characters(&record, None, 1, &options, &mut errors);
+        let (errors, _) = errors.into_parts();
+        let errors: Vec<String> = errors.iter().map(|e| e.error.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "NUL byte: U+0000 in column 1 at byte 1",
+                "invisible character: U+200B in column 3 at byte 4",
+                "bidi control character: U+202E in column 4 at byte 0",
+            ]
+        );
+
+        // Allowed characters are not reported; replacing the defaults disallows the tab
+        let options = ValidationOptions {
+            characters: true,
+            allowed_characters: vec![0..=0, 0x200b..=0x200b, 0x202e..=0x202e],
+            ..Default::default()
+        };
+        let mut errors = ErrorCollector::new(&limits);
+        validate_characters(&record, None, 1, &options, &mut errors);
+        assert_eq!(errors.into_parts().0.len(), 1);
+    }
+
+    #[test]
+    fn test_validate_surrogates() {
+        let options = ValidationOptions {
+            characters: true,
+            ..Default::default()
+        };
+        let limits = ErrorLimits::default();
+
+        let mut errors = ErrorCollector::new(&limits);
+        assert!(validate_surrogates(
+            b"ok,a\xed\xa0\xbdb\n",
+            None,
+            3,
+            &options,
+            &mut errors
+        ));
+        let (errors, _) = errors.into_parts();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error.to_string(),
+            "lone surrogate: U+D83D in column 2 at byte 1"
+        );
+
+        // Other invalid UTF-8 is left to the caller
+        let mut errors = ErrorCollector::new(&limits);
+        assert!(!validate_surrogates(
+            b"a\xff\n",
+            None,
+            3,
+            &options,
+            &mut errors
+        ));
+    }
+}
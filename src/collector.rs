@@ -0,0 +1,98 @@
+//! Collection of validation errors subject to the configured limits
+
+use crate::CsvError;
+use crate::options::ErrorLimits;
+use std::collections::HashMap;
+
+/// Collects errors, dropping those that exceed the configured limits
+#[derive(Debug)]
+pub(crate) struct ErrorCollector<'a> {
+    limits: &'a ErrorLimits,
+    errors: Vec<CsvError>,
+    per_kind: HashMap<&'static str, usize>,
+    truncated: bool,
+}
+
+impl<'a> ErrorCollector<'a> {
+    pub fn new(limits: &'a ErrorLimits) -> Self {
+        ErrorCollector {
+            limits,
+            errors: Vec::new(),
+            per_kind: HashMap::new(),
+            truncated: false,
+        }
+    }
+
+    /// Records an error, unless a limit has been reached
+    pub fn push(&mut self, error: CsvError) {
+        if self.is_full() {
+            self.truncated = true;
+            return;
+        }
+
+        let name = error.error.name();
+        let count = self.per_kind.entry(name).or_insert(0);
+        if let Some(&cap) = self.limits.max_per_kind.get(name)
+            && *count >= cap
+        {
+            self.truncated = true;
+            return;
+        }
+
+        *count += 1;
+        self.errors.push(error);
+    }
+
+    /// Whether the overall error limit has been reached
+    pub fn is_full(&self) -> bool {
+        self.limits
+            .max_errors
+            .is_some_and(|max| self.errors.len() >= max)
+    }
+
+    /// Marks the collected errors as incomplete
+    pub fn set_truncated(&mut self) {
+        self.truncated = true;
+    }
+
+    pub fn into_parts(self) -> (Vec<CsvError>, bool) {
+        (self.errors, self.truncated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CsvErrorKind;
+
+    fn error(record_num: usize, kind: CsvErrorKind) -> CsvError {
+        CsvError {
+            record: None,
+            record_num,
+            error: kind,
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = ErrorLimits {
+            max_errors: Some(3),
+            max_per_kind: HashMap::from([("InvalidLineEnding".to_string(), 1)]),
+        };
+        let mut collector = ErrorCollector::new(&limits);
+        collector.push(error(1, CsvErrorKind::InvalidLineEnding));
+        collector.push(error(2, CsvErrorKind::InvalidLineEnding));
+        collector.push(error(3, CsvErrorKind::FieldCount));
+        assert!(!collector.is_full());
+        collector.push(error(4, CsvErrorKind::FieldCount));
+        assert!(collector.is_full());
+        collector.push(error(5, CsvErrorKind::FieldCount));
+
+        let (errors, truncated) = collector.into_parts();
+        assert!(truncated);
+        assert_eq!(
+            errors.iter().map(|e| e.record_num).collect::<Vec<_>>(),
+            vec![1, 3, 4]
+        );
+    }
+}
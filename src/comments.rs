@@ -0,0 +1,65 @@
+//! Comment line rule
+//!
+//! RFC 4180 has no comments, so in strict mode every comment line is
+//! reported. Lines are numbered the same way as by the blank line rules.
+
+use crate::collector::ErrorCollector;
+use crate::line_endings::count_line_breaks;
+use crate::{CsvError, CsvErrorKind, ValidationOptions, tokenizer};
+
+/// Reports the comment lines of `content` when comments are enabled in RFC 4180 mode
+///
+/// `first_line` is the line number of the first line in `content`.
+pub(crate) fn validate_comments(
+    content: &[u8],
+    first_line: usize,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) {
+    let Some(comment) = options.comment else {
+        return;
+    };
+    if !options.rfc4180_mode {
+        return;
+    }
+
+    let mut line_num = first_line;
+    let mut counted = 0;
+    tokenizer::comments(content, options, comment, |span| {
+        line_num += count_line_breaks(&content[counted..span.start]);
+        counted = span.start;
+        if options.sample.includes(line_num) {
+            errors.push(CsvError {
+                record: None,
+                record_num: line_num,
+                error: CsvErrorKind::CommentLine,
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorLimits;
+
+    #[test]
+    fn test_comment_lines() {
+        let content = b"# title\r\na,b\r\n\"x\r\n# y\",1\r\n#\r\n2,3\r\n";
+        let limits = ErrorLimits::default();
+        let check = |options: &ValidationOptions| {
+            let mut errors = ErrorCollector::new(&limits);
+            validate_comments(content, 1, options, &mut errors);
+            let (errors, _) = errors.into_parts();
+            errors.iter().map(|e| e.record_num).collect::<Vec<_>>()
+        };
+
+        let mut options = ValidationOptions {
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        assert!(check(&options).is_empty());
+        options.rfc4180_mode = true;
+        assert_eq!(check(&options), vec![1, 5]);
+    }
+}
@@ -0,0 +1,203 @@
+//! Transparent decompression of compressed CSV inputs
+//!
+//! Compression is detected from the leading magic bytes of the stream, which
+//! every supported format starts with; the file extension is ignored. Each codec is gated behind its own cargo feature
+//! (`gzip`, `zstd`, `bzip2`, `xz`); inputs in a format whose codec wasn't
+//! compiled in are read as plain text.
+
+use std::io::{self, BufReader, Read};
+
+/// Number of leading bytes needed to recognise every format; bzip2 has the longest magic
+const MAGIC_LEN: usize = 10;
+
+/// Compression formats csvlint knows how to recognise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Detects the compression format from the leading bytes of a stream
+    pub fn from_magic(magic: &[u8]) -> Option<Compression> {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if is_bzip2(magic) {
+            Some(Compression::Bzip2)
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else {
+            None
+        }
+    }
+
+    /// Detects the compression format from the leading bytes of a stream
+    ///
+    /// Formats whose codec wasn't compiled in are detected as [`Compression::None`].
+    pub fn detect(magic: &[u8]) -> Compression {
+        Compression::from_magic(magic)
+            .filter(|compression| compression.is_supported())
+            .unwrap_or(Compression::None)
+    }
+
+    /// Whether the codec for this format was compiled in
+    pub fn is_supported(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+            Compression::Bzip2 => cfg!(feature = "bzip2"),
+            Compression::Xz => cfg!(feature = "xz"),
+        }
+    }
+}
+
+/// Whether `magic` starts with a bzip2 stream header: `BZh`, the block size
+/// digit and the magic of the first block, or of the end of an empty stream
+///
+/// `BZh` alone also starts plain text, such as a header cell.
+fn is_bzip2(magic: &[u8]) -> bool {
+    const BLOCK: &[u8] = &[0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+    const END_OF_STREAM: &[u8] = &[0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+    match magic {
+        [b'B', b'Z', b'h', b'1'..=b'9', rest @ ..] => {
+            rest.starts_with(BLOCK) || rest.starts_with(END_OF_STREAM)
+        }
+        _ => false,
+    }
+}
+
+/// Wraps `reader` in the matching decompressor, if the input is compressed
+///
+/// Inputs whose codec was not compiled in are passed through as plain text.
+pub fn decompress<'a, R: Read + 'a>(
+    mut reader: R,
+) -> io::Result<(Compression, Box<dyn Read + 'a>)> {
+    // A pipe can return fewer bytes than the magic from a single read
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    (&mut reader)
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut magic)?;
+    let compression = Compression::detect(&magic);
+    let reader = BufReader::new(io::Cursor::new(magic).chain(reader));
+
+    let wrapped: Box<dyn Read + 'a> = match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(reader)),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+        _ => Box::new(reader),
+    };
+
+    Ok((compression, wrapped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_detect_compression() {
+        assert_eq!(
+            Compression::from_magic(&[0x1f, 0x8b, 0x08]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_magic(&[0x28, 0xb5, 0x2f, 0xfd]),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::from_magic(b"BZh91AY&SY\x00"),
+            Some(Compression::Bzip2)
+        );
+        assert_eq!(
+            Compression::from_magic(b"BZh9\x17rE8P\x90"),
+            Some(Compression::Bzip2)
+        );
+        assert_eq!(Compression::from_magic(b"BZhName,b\n1,2\n"), None);
+        assert_eq!(Compression::from_magic(b"BZh01AY&SY"), None);
+        assert_eq!(Compression::detect(b"a,b,c"), Compression::None);
+    }
+
+    #[test]
+    fn test_plain_input_passes_through() {
+        let (compression, mut reader) = decompress(Cursor::new("a,b\r\n1,2\r\n")).unwrap();
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(compression, Compression::None);
+        assert_eq!(out, "a,b\r\n1,2\r\n");
+    }
+
+    #[cfg(not(feature = "bzip2"))]
+    #[test]
+    fn test_unsupported_codec_reads_plain_text() {
+        let content = b"BZh91AY&SY,b\n1,2\n";
+        assert_eq!(Compression::detect(content), Compression::None);
+        let (compression, mut reader) = decompress(Cursor::new(content)).unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(compression, Compression::None);
+        assert_eq!(out, content);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_roundtrip() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"a,b\r\n1,2\r\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let (compression, mut reader) = decompress(Cursor::new(compressed)).unwrap();
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(compression, Compression::Gzip);
+        assert_eq!(out, "a,b\r\n1,2\r\n");
+    }
+
+    /// A reader returning one byte per read, like a slow pipe
+    struct OneByte<R>(R);
+
+    impl<R: Read> Read for OneByte<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn test_magic_split_across_reads() {
+        use std::io::Write;
+
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(b"a,b\r\n1,2\r\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let (compression, mut reader) = decompress(OneByte(Cursor::new(compressed))).unwrap();
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(compression, Compression::Bzip2);
+        assert_eq!(out, "a,b\r\n1,2\r\n");
+    }
+
+    #[test]
+    fn test_short_input_passes_through() {
+        let (compression, mut reader) = decompress(OneByte(Cursor::new("BZh"))).unwrap();
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(compression, Compression::None);
+        assert_eq!(out, "BZh");
+    }
+}
@@ -0,0 +1,171 @@
+//! Fix mode
+//!
+//! Rewrites a file with its fixable errors corrected. Only the affected fields
+//! are edited; everything else, including quoting and line endings, is copied
+//! byte for byte.
+
+use crate::formula::is_formula;
+use crate::{ValidationOptions, csv_reader_builder, skip, tokenizer};
+use std::ops::Range;
+
+/// A file with its fixable errors corrected
+#[derive(Debug)]
+pub struct FixResult {
+    /// The corrected content
+    pub content: Vec<u8>,
+    /// Number of fields changed
+    pub fixes: usize,
+}
+
+/// A replacement of part of the content
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    /// The byte range replaced
+    pub range: Range<usize>,
+    /// The replacement
+    pub text: String,
+}
+
+/// Corrects the fixable errors reported by the rules enabled in `options`
+///
+/// See [`fix_edits`] for what is corrected.
+pub fn fix_slice(content: &[u8], options: &ValidationOptions) -> FixResult {
+    let edits = fix_edits(content, options);
+    let mut fixed = Vec::with_capacity(content.len());
+    // content[..copied] has been written to `fixed`
+    let mut copied = 0;
+    for edit in &edits {
+        fixed.extend_from_slice(&content[copied..edit.range.start]);
+        fixed.extend_from_slice(edit.text.as_bytes());
+        copied = edit.range.end;
+    }
+    fixed.extend_from_slice(&content[copied..]);
+
+    FixResult {
+        content: fixed,
+        fixes: edits.len(),
+    }
+}
+
+/// The edits correcting the fixable errors reported by the rules enabled in `options`, in order
+///
+/// Leading and trailing whitespace is trimmed from the fields checked by
+/// [`ValidationOptions::surrounding_whitespace`], and fields flagged by
+/// [`ValidationOptions::formula_injection`] are prefixed with `'` so that
+/// spreadsheets show them as text. The header is left unchanged, as are
+/// skipped lines and everything after the first parse error. Fixed-width
+/// files are never edited, since that would shift their columns.
+pub fn fix_edits(content: &[u8], options: &ValidationOptions) -> Vec<Edit> {
+    let quoting = !options.lazy_quotes;
+    let mut edits = Vec::new();
+    if options.fixed_width.is_some() {
+        return edits;
+    }
+    let data = skip::data(content, options).range;
+    let offset = data.start;
+    let content = &content[data];
+
+    for record in data_records(content, options) {
+        let start = record.start;
+        for span in tokenizer::fields(&content[record], options) {
+            let quoted = quoting && content.get(start + span.start) == Some(&b'"');
+            // The value without its quotes; escaped quotes are left as they are
+            let inner = if quoted && span.len() >= 2 {
+                start + span.start + 1..start + span.end - 1
+            } else {
+                start + span.start..start + span.end
+            };
+            let Ok(value) = std::str::from_utf8(&content[inner.clone()]) else {
+                continue;
+            };
+
+            let trimmed = if options.surrounding_whitespace.includes(quoted) {
+                value.trim()
+            } else {
+                value
+            };
+            // Checked before trimming, like the rule, so that only reported values are prefixed
+            let formula = options.formula_injection && is_formula(value);
+            if trimmed.len() == value.len() && !formula {
+                continue;
+            }
+
+            let prefix = if formula { "'" } else { "" };
+            edits.push(Edit {
+                range: offset + inner.start..offset + inner.end,
+                text: format!("{}{}", prefix, trimmed),
+            });
+        }
+    }
+
+    edits
+}
+
+/// Byte ranges of the data records of `content`, up to the first parse error
+fn data_records(content: &[u8], options: &ValidationOptions) -> Vec<Range<usize>> {
+    let mut records = Vec::new();
+    if options.custom_delimiter.is_some() {
+        tokenizer::records(content, options, |span| records.push(span));
+    } else {
+        let mut csv_reader = csv_reader_builder(options).from_reader(content);
+        let mut record = csv::ByteRecord::new();
+        loop {
+            let start = csv_reader.position().byte() as usize;
+            match csv_reader.read_byte_record(&mut record) {
+                Ok(true) => records.push(start..csv_reader.position().byte() as usize),
+                Ok(false) | Err(_) => break,
+            }
+        }
+    }
+    // The header is not a data record
+    records.into_iter().skip(1).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FieldScope;
+
+    #[test]
+    fn test_fix_slice() {
+        let options = ValidationOptions {
+            surrounding_whitespace: FieldScope::All,
+            formula_injection: true,
+            ..Default::default()
+        };
+        let content = b" id ,cmd\r\n1,=1+1\r\n\r\n2,\"@SUM(A1) \"\r\n3,-42\r\n4,\"a \"\"b\"\" \"";
+        let result = fix_slice(content, &options);
+        assert_eq!(
+            String::from_utf8(result.content).unwrap(),
+            " id ,cmd\r\n1,'=1+1\r\n\r\n2,\"'@SUM(A1)\"\r\n3,-42\r\n4,\"a \"\"b\"\"\""
+        );
+        assert_eq!(result.fixes, 3);
+
+        // Nothing to fix leaves the content untouched
+        let result = fix_slice(content, &ValidationOptions::default());
+        assert_eq!(result.content, content);
+        assert_eq!(result.fixes, 0);
+    }
+
+    #[test]
+    fn test_fix_only_reported_formulas() {
+        let mut options = ValidationOptions {
+            formula_injection: true,
+            ..Default::default()
+        };
+        // The formula rule doesn't report a value starting with a space
+        let content = b"a,b\n =1,\t=1\n";
+        let result = fix_slice(content, &options);
+        assert_eq!(
+            String::from_utf8(result.content).unwrap(),
+            "a,b\n =1,'\t=1\n"
+        );
+        assert_eq!(result.fixes, 1);
+
+        // Trimming fixes the whitespace error without adding a prefix
+        options.surrounding_whitespace = FieldScope::All;
+        let result = fix_slice(content, &options);
+        assert_eq!(String::from_utf8(result.content).unwrap(), "a,b\n=1,'=1\n");
+        assert_eq!(result.fixes, 2);
+    }
+}
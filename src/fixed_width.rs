@@ -0,0 +1,379 @@
+//! Fixed-width file validation
+//!
+//! Fixed-width files have no delimiters: each column sits at the same
+//! character positions on every line, padded to its width. The columns are
+//! declared in a spec file, itself a CSV file with one row per column:
+//!
+//! ```text
+//! name,start,width,type,align,padding
+//! id,1,5,integer,right,0
+//! name,6,20,string,,
+//! amount,26,10,float,,
+//! ```
+//!
+//! `start` counts characters from 1 and `type` is one of `string`,
+//! `integer`, `float`, `boolean` and `date`. The optional `align` is `left`
+//! or `right`, by default right for numbers and left otherwise, and the
+//! optional `padding` is the character filling the rest of the column, a
+//! space by default.
+//!
+//! Every line is a record; there is no header, which can be skipped like any
+//! preamble. Errors report physical line numbers, and the fields of the line
+//! without their padding.
+
+use crate::collector::ErrorCollector;
+use crate::line_endings::{LineEndingStats, expected_line_ending, validate_line_endings};
+use crate::stats::ValueType;
+use crate::{Column, CsvError, CsvErrorKind, Sample, ValidationOptions, ValidationResult, skip};
+use csv::StringRecord;
+use serde::Deserialize;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Errors loading a spec file
+#[derive(Debug, Error)]
+pub enum SpecError {
+    #[error("cannot read '{0}': {1}")]
+    Io(PathBuf, io::Error),
+    #[error("'{0}': {1}")]
+    Csv(PathBuf, csv::Error),
+    #[error("'{0}': {1}")]
+    Invalid(PathBuf, String),
+}
+
+/// Which side of a column its value is written against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// One column of a fixed-width file
+#[derive(Debug, Clone, Deserialize)]
+pub struct FixedWidthColumn {
+    pub name: String,
+    /// Position of the first character, counting from 1
+    pub start: usize,
+    /// Number of characters
+    pub width: usize,
+    /// Type every non-empty value must parse as
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+    /// Side the value is written against; right for numbers and left otherwise if not set
+    #[serde(default)]
+    pub align: Option<Align>,
+    /// Character filling the rest of the column; a space if not set
+    #[serde(default)]
+    pub padding: Option<char>,
+}
+
+impl FixedWidthColumn {
+    fn align(&self) -> Align {
+        self.align.unwrap_or(match self.value_type {
+            ValueType::Integer | ValueType::Float => Align::Right,
+            _ => Align::Left,
+        })
+    }
+
+    fn padding(&self) -> char {
+        self.padding.unwrap_or(' ')
+    }
+
+    fn end(&self) -> usize {
+        self.start - 1 + self.width
+    }
+}
+
+/// The columns of a fixed-width file
+#[derive(Debug, Clone)]
+pub struct FixedWidthSpec {
+    pub columns: Vec<FixedWidthColumn>,
+    /// The column names, for reporting
+    header: StringRecord,
+}
+
+impl FixedWidthSpec {
+    /// Loads a spec file
+    pub fn load(path: &Path) -> Result<FixedWidthSpec, SpecError> {
+        let file = File::open(path).map_err(|e| SpecError::Io(path.to_path_buf(), e))?;
+        let columns = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(file)
+            .deserialize()
+            .collect::<Result<Vec<FixedWidthColumn>, _>>()
+            .map_err(|e| SpecError::Csv(path.to_path_buf(), e))?;
+        FixedWidthSpec::new(columns).map_err(|e| SpecError::Invalid(path.to_path_buf(), e))
+    }
+
+    /// Checks that the columns are non-empty and don't overlap
+    pub fn new(mut columns: Vec<FixedWidthColumn>) -> Result<FixedWidthSpec, String> {
+        if columns.is_empty() {
+            return Err("no columns declared".to_string());
+        }
+        for column in &columns {
+            if column.start == 0 || column.width == 0 {
+                return Err(format!(
+                    "column {:?} must start at 1 or later and be at least 1 character wide",
+                    column.name
+                ));
+            }
+        }
+        columns.sort_by_key(|column| column.start);
+        for pair in columns.windows(2) {
+            if pair[1].start <= pair[0].end() {
+                return Err(format!(
+                    "columns {:?} and {:?} overlap",
+                    pair[0].name, pair[1].name
+                ));
+            }
+        }
+
+        let header = columns.iter().map(|column| column.name.as_str()).collect();
+        Ok(FixedWidthSpec { columns, header })
+    }
+
+    /// Number of characters of every line
+    pub fn line_length(&self) -> usize {
+        self.columns.last().map_or(0, FixedWidthColumn::end)
+    }
+}
+
+/// Validates a fixed-width file against `spec`
+///
+/// Line endings are checked as for CSV files; the other rules of `options`
+/// are about fields, and don't apply.
+pub(crate) fn validate(
+    content: &[u8],
+    spec: &FixedWidthSpec,
+    options: &ValidationOptions,
+) -> ValidationResult {
+    let data = skip::data(content, options);
+    let content = &content[data.range];
+    let lines = skip::lines(content);
+    let (lines, content) = match options.sample {
+        Sample::FirstRows(rows) if rows < lines.len() => {
+            (&lines[..rows], &content[..lines[rows].start])
+        }
+        _ => (&lines[..], content),
+    };
+    let mut errors = ErrorCollector::new(&options.limits);
+
+    let line_endings = LineEndingStats::from_content(content, options);
+    let expected = expected_line_ending(&line_endings, options);
+    validate_line_endings(content, data.first_line, options, expected, &mut errors);
+
+    for (i, line) in lines.iter().enumerate() {
+        if errors.is_full() {
+            errors.set_truncated();
+            break;
+        }
+        let line_num = data.first_line + i;
+        if !options.sample.includes(line_num) {
+            continue;
+        }
+        let text = match std::str::from_utf8(&content[line.clone()]) {
+            Ok(text) => text,
+            Err(e) => {
+                errors.push(CsvError {
+                    record: None,
+                    record_num: line_num,
+                    error: CsvErrorKind::Utf8(format!(
+                        "invalid UTF-8 on line {} near byte index {}",
+                        line_num,
+                        e.valid_up_to()
+                    )),
+                });
+                return ValidationResult::from_collector(errors, true, line_endings);
+            }
+        };
+        validate_line(text, line_num, spec, &mut errors);
+    }
+
+    ValidationResult::from_collector(errors, false, line_endings)
+}
+
+/// Checks the length and fields of one line
+fn validate_line(text: &str, line_num: usize, spec: &FixedWidthSpec, errors: &mut ErrorCollector) {
+    let chars: Vec<char> = text.chars().collect();
+    let fields: Vec<Option<String>> = spec
+        .columns
+        .iter()
+        .map(|column| {
+            let field = chars.get(column.start - 1..column.end())?;
+            Some(field.iter().collect())
+        })
+        .collect();
+    let values = || {
+        spec.columns
+            .iter()
+            .zip(&fields)
+            .map(|(column, field)| {
+                field
+                    .as_deref()
+                    .map_or("", |f| strip(f, column))
+                    .to_string()
+            })
+            .collect()
+    };
+    let mut push = |error: CsvErrorKind| {
+        errors.push(CsvError {
+            record: Some(values()),
+            record_num: line_num,
+            error,
+        });
+    };
+
+    if chars.len() != spec.line_length() {
+        push(CsvErrorKind::LineLength {
+            length: chars.len(),
+            expected: spec.line_length(),
+        });
+    }
+
+    // Fields cut off by a short line are covered by its length error
+    for (i, (column, field)) in spec.columns.iter().zip(&fields).enumerate() {
+        let Some(field) = field else {
+            continue;
+        };
+        let column_ref = || Column::new(i, Some(&spec.header));
+        let padding = column.padding();
+        let blank = |c: char| c == padding || c.is_whitespace() || c == '\0';
+
+        // Padding is on the side away from the alignment, and anything else
+        // blank there is padding with the wrong character
+        let (value, misaligned) = match column.align() {
+            Align::Left => {
+                let value = field.trim_end_matches(padding);
+                (
+                    value,
+                    value.starts_with(blank) && !value.trim_matches(blank).is_empty(),
+                )
+            }
+            Align::Right => {
+                let value = field.trim_start_matches(padding);
+                (
+                    value,
+                    value.ends_with(blank) && !value.trim_matches(blank).is_empty(),
+                )
+            }
+        };
+        let wrong_padding = match column.align() {
+            Align::Left => value.ends_with(blank),
+            Align::Right => value.starts_with(blank),
+        };
+        if wrong_padding {
+            push(CsvErrorKind::Padding(column_ref()));
+        }
+        if misaligned {
+            push(CsvErrorKind::Alignment(column_ref()));
+        }
+
+        let value = value.trim_matches(blank);
+        if !value.is_empty() && !column.value_type.matches(value) {
+            push(CsvErrorKind::FieldType {
+                column: column_ref(),
+                expected: column.value_type,
+            });
+        }
+    }
+}
+
+/// The value of a field without its padding, or any other blank around it
+fn strip<'a>(field: &'a str, column: &FixedWidthColumn) -> &'a str {
+    let padding = column.padding();
+    field.trim_matches(|c: char| c == padding || c.is_whitespace() || c == '\0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate_slice_with_options;
+
+    fn column(name: &str, start: usize, width: usize, value_type: ValueType) -> FixedWidthColumn {
+        FixedWidthColumn {
+            name: name.to_string(),
+            start,
+            width,
+            value_type,
+            align: None,
+            padding: None,
+        }
+    }
+
+    #[test]
+    fn test_spec() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spec.csv");
+        std::fs::write(
+            &path,
+            "name,start,width,type,align,padding\namount,6,4,float,,\nid,1,5,integer,right,0\n",
+        )
+        .unwrap();
+        let spec = FixedWidthSpec::load(&path).unwrap();
+        assert_eq!(spec.columns[0].name, "id");
+        assert_eq!(spec.columns[0].padding(), '0');
+        assert_eq!(spec.columns[1].align(), Align::Right);
+        assert_eq!(spec.line_length(), 9);
+
+        std::fs::write(&path, "name,start,width,type\na,1,5,string\nb,5,2,date\n").unwrap();
+        assert!(matches!(
+            FixedWidthSpec::load(&path),
+            Err(SpecError::Invalid(_, message)) if message == "columns \"a\" and \"b\" overlap"
+        ));
+        std::fs::write(&path, "name,start,width,type\na,1,5,text\n").unwrap();
+        assert!(matches!(
+            FixedWidthSpec::load(&path),
+            Err(SpecError::Csv(..))
+        ));
+    }
+
+    #[test]
+    fn test_fixed_width() {
+        let spec = FixedWidthSpec::new(vec![
+            FixedWidthColumn {
+                padding: Some('0'),
+                ..column("id", 1, 3, ValueType::Integer)
+            },
+            column("name", 4, 6, ValueType::String),
+            column("joined", 10, 10, ValueType::Date),
+            column("score", 20, 4, ValueType::Float),
+        ])
+        .unwrap();
+        let options = ValidationOptions {
+            fixed_width: Some(spec),
+            ..Default::default()
+        };
+        let content = concat!(
+            "001Ann   2024-01-31 1.5\n",
+            "002 Bob  2024-02-30   2\n",
+            " 03Cy\t   2024-03-01  x \n",
+            "004Dee   2024-04-01\n",
+        );
+        let result = validate_slice_with_options(content.as_bytes(), &options).unwrap();
+        let reported: Vec<String> = result.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            reported,
+            vec![
+                "Line #2 has error: misaligned value in column 2 (\"name\")",
+                "Line #2 has error: value in column 3 (\"joined\") is not a valid date",
+                "Line #3 has error: wrong padding in column 1 (\"id\")",
+                "Line #3 has error: wrong padding in column 2 (\"name\")",
+                "Line #3 has error: misaligned value in column 4 (\"score\")",
+                "Line #3 has error: value in column 4 (\"score\") is not a valid float",
+                "Line #4 has error: line is 19 characters long, expected 23",
+            ]
+        );
+        assert_eq!(
+            result.errors[0].record,
+            Some(vec![
+                "2".to_string(),
+                "Bob".to_string(),
+                "2024-02-30".to_string(),
+                "2".to_string()
+            ])
+        );
+    }
+}
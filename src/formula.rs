@@ -0,0 +1,82 @@
+//! CSV injection rule
+//!
+//! Spreadsheets such as Excel evaluate cells starting with `=`, `+`, `-` or
+//! `@` as formulas, and a leading tab or CR can hide such a prefix. Numbers
+//! like `-42` or `+1.5` are allowed, since they are what numeric columns hold.
+
+use crate::collector::ErrorCollector;
+use crate::{Column, CsvError, CsvErrorKind};
+use csv::StringRecord;
+
+/// Characters that make a spreadsheet treat a cell as a formula
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Whether `value` could be evaluated as a formula when opened in a spreadsheet
+pub fn is_formula(value: &str) -> bool {
+    value.starts_with(FORMULA_PREFIXES) && !is_number(value)
+}
+
+/// Whether `value` is a plain decimal number, optionally signed and with an exponent
+pub(crate) fn is_number(value: &str) -> bool {
+    let value = value.strip_prefix(['+', '-']).unwrap_or(value);
+    let (mantissa, exponent) = match value.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (value, None),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    let exponent_ok = exponent.is_none_or(|e| {
+        let e = e.strip_prefix(['+', '-']).unwrap_or(e);
+        !e.is_empty() && digits(e)
+    });
+
+    !(integer.is_empty() && fraction.is_empty())
+        && digits(integer)
+        && digits(fraction)
+        && exponent_ok
+}
+
+/// Flags the fields of one record that could be evaluated as formulas
+pub(crate) fn validate_formulas(
+    record: &StringRecord,
+    header: Option<&StringRecord>,
+    record_num: usize,
+    errors: &mut ErrorCollector,
+) {
+    for (i, field) in record.iter().enumerate() {
+        if is_formula(field) {
+            errors.push(CsvError {
+                record: Some(record.iter().map(|s| s.to_string()).collect()),
+                record_num,
+                error: CsvErrorKind::FormulaInjection(Column::new(i, header)),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_formula() {
+        for value in [
+            "=1+1",
+            "@SUM(A1)",
+            "+cmd",
+            "-",
+            "-2+3",
+            "\t=1",
+            "\r=1",
+            "=HYPERLINK(\"x\")",
+        ] {
+            assert!(is_formula(value), "{:?}", value);
+        }
+        for value in [
+            "-42", "+1.5", "-.5", "-1e10", "-2.5E-3", "a=b", "", "1-2", " =1",
+        ] {
+            assert!(!is_formula(value), "{:?}", value);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Reading file versions from git
+//!
+//! The `git` binary is run in the directory of the file, so that paths are
+//! resolved relative to the repository the file belongs to.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use thiserror::Error;
+
+/// Errors running git
+#[derive(Debug, Error)]
+pub enum GitError {
+    #[error("cannot run git: {0}")]
+    Spawn(io::Error),
+    #[error("unknown git revision '{0}'")]
+    UnknownRevision(String),
+    #[error("git {0} failed: {1}")]
+    Failed(String, String),
+}
+
+/// The content of the file at `path` in `revision`, or `None` if it didn't exist there
+pub fn file_at_revision(path: &Path, revision: &str) -> Result<Option<Vec<u8>>, GitError> {
+    let commit = format!("{}^{{commit}}", revision);
+    if git(path, &["rev-parse", "--verify", "--quiet", &commit]).is_err() {
+        return Err(GitError::UnknownRevision(revision.to_string()));
+    }
+    blob(path, &format!("{}:./{}", revision, file_name(path)))
+}
+
+/// The content of the file at `path` as staged in the index, or `None` if it isn't there
+///
+/// Fails if `path` isn't in a git repository.
+pub fn staged_file(path: &Path) -> Result<Option<Vec<u8>>, GitError> {
+    git(path, &["rev-parse", "--git-dir"])?;
+    blob(path, &format!(":./{}", file_name(path)))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The content of a blob, or `None` if there is no such blob
+fn blob(path: &Path, object: &str) -> Result<Option<Vec<u8>>, GitError> {
+    if git(path, &["cat-file", "-e", object]).is_err() {
+        return Ok(None);
+    }
+    git(path, &["cat-file", "blob", object]).map(Some)
+}
+
+/// Runs git in the directory of `path`, returning its output
+fn git(path: &Path, args: &[&str]) -> Result<Vec<u8>, GitError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(GitError::Spawn)?;
+    if !output.status.success() {
+        return Err(GitError::Failed(
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::git;
+    use std::fs;
+
+    #[test]
+    fn test_file_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        git(dir.path(), &["init", "--quiet"]);
+        fs::write(&path, "a,b\n1,2\n").unwrap();
+        git(dir.path(), &["add", "data.csv"]);
+        git(dir.path(), &["commit", "--quiet", "-m", "add data"]);
+        fs::write(&path, "a,b\n1,2\n3,4\n").unwrap();
+        git(dir.path(), &["add", "data.csv"]);
+        fs::write(&path, "a,b\n").unwrap();
+
+        assert_eq!(
+            file_at_revision(&path, "HEAD").unwrap().unwrap(),
+            b"a,b\n1,2\n"
+        );
+        assert_eq!(staged_file(&path).unwrap().unwrap(), b"a,b\n1,2\n3,4\n");
+        assert_eq!(staged_file(&dir.path().join("new.csv")).unwrap(), None);
+        assert_eq!(
+            file_at_revision(&dir.path().join("new.csv"), "HEAD").unwrap(),
+            None
+        );
+        assert!(matches!(
+            file_at_revision(&path, "no-such-branch"),
+            Err(GitError::UnknownRevision(_))
+        ));
+    }
+
+    #[test]
+    fn test_outside_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        fs::write(&path, "a,b\n").unwrap();
+        assert!(matches!(
+            staged_file(&path),
+            Err(GitError::Failed(command, _)) if command == "rev-parse --git-dir"
+        ));
+    }
+}
@@ -1,6 +1,57 @@
+pub mod baseline;
+pub mod blank_lines;
+pub mod changes;
+pub mod characters;
+mod collector;
+mod comments;
+pub mod decompress;
+pub mod fix;
+pub mod fixed_width;
+pub mod formula;
+pub mod git;
+pub mod line_endings;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod options;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod report;
+pub mod schema;
+pub mod size_limits;
+pub mod skip;
+pub mod stats;
+pub mod structural;
+pub mod suppress;
+#[cfg(test)]
+mod test_support;
+pub mod tokenizer;
+pub mod unique;
+#[cfg(feature = "watch")]
+pub mod watch;
+pub mod whitespace;
+
+pub use blank_lines::FinalNewline;
+use blank_lines::validate_blank_lines;
+pub use characters::Character;
+use characters::{validate_characters, validate_record_surrogates, validate_surrogates};
+use collector::ErrorCollector;
+use comments::validate_comments;
 use csv::{ReaderBuilder, StringRecord};
+use formula::validate_formulas;
+use line_endings::{LineEnding, LineEndingStats, expected_line_ending, validate_line_endings};
+pub use options::{ErrorLimits, Sample, ValidationOptions};
+use schema::ForeignKeyChecker;
+use size_limits::FieldLimits;
+pub use size_limits::{FieldLengthLimit, LengthUnit, SizeLimits};
+use stats::ValueType;
 use std::io::Read;
 use thiserror::Error;
+use unique::UniqueChecker;
+pub use unique::{FirstOccurrence, UniqueMode};
+pub use whitespace::FieldScope;
+use whitespace::{quoted_fields, validate_whitespace};
 
 /// Error information about an invalid record in a CSV file
 #[derive(Debug, Clone, PartialEq)]
@@ -13,6 +64,32 @@ pub struct CsvError {
     pub error: CsvErrorKind,
 }
 
+/// A column of the file: its 1-based position and, if there is a header, its name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub index: usize,
+    pub name: Option<String>,
+}
+
+impl Column {
+    /// The column at 0-based position `i`, named from `header`
+    pub(crate) fn new(i: usize, header: Option<&StringRecord>) -> Self {
+        Column {
+            index: i + 1,
+            name: header.and_then(|h| h.get(i)).map(|name| name.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "column {} ({:?})", self.index, name),
+            None => write!(f, "column {}", self.index),
+        }
+    }
+}
+
 /// Types of CSV validation errors
 #[derive(Debug, Clone, PartialEq, Error)]
 pub enum CsvErrorKind {
@@ -28,6 +105,70 @@ pub enum CsvErrorKind {
     UnterminatedQuote,
     #[error("invalid line ending (RFC 4180 requires CRLF)")]
     InvalidLineEnding,
+    #[error("mixed line endings: found {found}, file predominantly uses {expected}")]
+    MixedLineEnding {
+        expected: LineEnding,
+        found: LineEnding,
+    },
+    #[error("blank line")]
+    BlankLine,
+    #[error("blank line at end of file")]
+    TrailingBlankLine,
+    #[error("missing line break at end of file")]
+    MissingFinalNewline,
+    #[error("line break at end of file")]
+    UnexpectedFinalNewline,
+    #[error("comment line (RFC 4180 has no comments)")]
+    CommentLine,
+    #[error("line is {length} characters long, expected {expected}")]
+    LineLength { length: usize, expected: usize },
+    #[error("wrong padding in {0}")]
+    Padding(Column),
+    #[error("misaligned value in {0}")]
+    Alignment(Column),
+    #[error("value in {column} is not a valid {expected}")]
+    FieldType { column: Column, expected: ValueType },
+    #[error("leading whitespace in {0}")]
+    LeadingWhitespace(Column),
+    #[error("trailing whitespace in {0}")]
+    TrailingWhitespace(Column),
+    #[error("non-breaking space in {0}")]
+    NonBreakingSpace(Column),
+    #[error("tab in {0}")]
+    Tab(Column),
+    #[error("possible formula injection in {0}")]
+    FormulaInjection(Column),
+    #[error("NUL byte: {0}")]
+    NulByte(Character),
+    #[error("control character: {0}")]
+    ControlCharacter(Character),
+    #[error("invisible character: {0}")]
+    InvisibleCharacter(Character),
+    #[error("bidi control character: {0}")]
+    BidiControl(Character),
+    #[error("lone surrogate: {0}")]
+    LoneSurrogate(Character),
+    #[error("duplicate row, {first}")]
+    DuplicateRow { first: FirstOccurrence },
+    #[error("duplicate key {key}, {first}")]
+    DuplicateKey { key: String, first: FirstOccurrence },
+    #[error("column {0:?} not found in header")]
+    UnknownColumn(String),
+    #[error("key {key} not found in {target}")]
+    MissingReference { key: String, target: String },
+    #[error("{column} is {length} {unit} long, limit is {max}")]
+    FieldTooLong {
+        column: Column,
+        length: usize,
+        max: usize,
+        unit: LengthUnit,
+    },
+    #[error("record has {count} fields, limit is {max}")]
+    TooManyFields { count: usize, max: usize },
+    #[error("record is {length} bytes long, limit is {max}; it was not parsed")]
+    RecordTooLong { length: usize, max: usize },
+    #[error("file has {count} records, limit is {max}")]
+    TooManyRecords { count: usize, max: usize },
     #[error("field contains unescaped special characters")]
     UnescapedSpecialChars,
     #[error("trailing comma found")]
@@ -38,9 +179,156 @@ pub enum CsvErrorKind {
     Utf8(String),
 }
 
+impl CsvErrorKind {
+    /// Names of all error kinds, as returned by [`CsvErrorKind::name`]
+    pub const NAMES: &'static [&'static str] = &[
+        "FieldCount",
+        "BareQuote",
+        "Quote",
+        "InvalidEscape",
+        "UnterminatedQuote",
+        "InvalidLineEnding",
+        "MixedLineEnding",
+        "BlankLine",
+        "TrailingBlankLine",
+        "MissingFinalNewline",
+        "UnexpectedFinalNewline",
+        "CommentLine",
+        "LineLength",
+        "Padding",
+        "Alignment",
+        "FieldType",
+        "LeadingWhitespace",
+        "TrailingWhitespace",
+        "NonBreakingSpace",
+        "Tab",
+        "FormulaInjection",
+        "NulByte",
+        "ControlCharacter",
+        "InvisibleCharacter",
+        "BidiControl",
+        "LoneSurrogate",
+        "DuplicateRow",
+        "DuplicateKey",
+        "UnknownColumn",
+        "MissingReference",
+        "FieldTooLong",
+        "TooManyFields",
+        "RecordTooLong",
+        "TooManyRecords",
+        "UnescapedSpecialChars",
+        "TrailingComma",
+        "Io",
+        "Utf8",
+    ];
+
+    /// Stable name of this error kind, used for per-kind limits and reporting
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsvErrorKind::FieldCount => "FieldCount",
+            CsvErrorKind::BareQuote => "BareQuote",
+            CsvErrorKind::Quote => "Quote",
+            CsvErrorKind::InvalidEscape => "InvalidEscape",
+            CsvErrorKind::UnterminatedQuote => "UnterminatedQuote",
+            CsvErrorKind::InvalidLineEnding => "InvalidLineEnding",
+            CsvErrorKind::MixedLineEnding { .. } => "MixedLineEnding",
+            CsvErrorKind::BlankLine => "BlankLine",
+            CsvErrorKind::TrailingBlankLine => "TrailingBlankLine",
+            CsvErrorKind::MissingFinalNewline => "MissingFinalNewline",
+            CsvErrorKind::UnexpectedFinalNewline => "UnexpectedFinalNewline",
+            CsvErrorKind::CommentLine => "CommentLine",
+            CsvErrorKind::LineLength { .. } => "LineLength",
+            CsvErrorKind::Padding(_) => "Padding",
+            CsvErrorKind::Alignment(_) => "Alignment",
+            CsvErrorKind::FieldType { .. } => "FieldType",
+            CsvErrorKind::LeadingWhitespace(_) => "LeadingWhitespace",
+            CsvErrorKind::TrailingWhitespace(_) => "TrailingWhitespace",
+            CsvErrorKind::NonBreakingSpace(_) => "NonBreakingSpace",
+            CsvErrorKind::Tab(_) => "Tab",
+            CsvErrorKind::FormulaInjection(_) => "FormulaInjection",
+            CsvErrorKind::NulByte(_) => "NulByte",
+            CsvErrorKind::ControlCharacter(_) => "ControlCharacter",
+            CsvErrorKind::InvisibleCharacter(_) => "InvisibleCharacter",
+            CsvErrorKind::BidiControl(_) => "BidiControl",
+            CsvErrorKind::LoneSurrogate(_) => "LoneSurrogate",
+            CsvErrorKind::DuplicateRow { .. } => "DuplicateRow",
+            CsvErrorKind::DuplicateKey { .. } => "DuplicateKey",
+            CsvErrorKind::UnknownColumn(_) => "UnknownColumn",
+            CsvErrorKind::MissingReference { .. } => "MissingReference",
+            CsvErrorKind::FieldTooLong { .. } => "FieldTooLong",
+            CsvErrorKind::TooManyFields { .. } => "TooManyFields",
+            CsvErrorKind::RecordTooLong { .. } => "RecordTooLong",
+            CsvErrorKind::TooManyRecords { .. } => "TooManyRecords",
+            CsvErrorKind::UnescapedSpecialChars => "UnescapedSpecialChars",
+            CsvErrorKind::TrailingComma => "TrailingComma",
+            CsvErrorKind::Io(_) => "Io",
+            CsvErrorKind::Utf8(_) => "Utf8",
+        }
+    }
+
+    /// The column the error is in, for kinds that report one
+    pub fn column(&self) -> Option<&Column> {
+        match self {
+            CsvErrorKind::LeadingWhitespace(column)
+            | CsvErrorKind::TrailingWhitespace(column)
+            | CsvErrorKind::NonBreakingSpace(column)
+            | CsvErrorKind::Tab(column)
+            | CsvErrorKind::FormulaInjection(column)
+            | CsvErrorKind::Padding(column)
+            | CsvErrorKind::Alignment(column)
+            | CsvErrorKind::FieldType { column, .. }
+            | CsvErrorKind::FieldTooLong { column, .. } => Some(column),
+            CsvErrorKind::NulByte(character)
+            | CsvErrorKind::ControlCharacter(character)
+            | CsvErrorKind::InvisibleCharacter(character)
+            | CsvErrorKind::BidiControl(character)
+            | CsvErrorKind::LoneSurrogate(character) => Some(&character.column),
+            _ => None,
+        }
+    }
+
+    /// Whether errors of this kind report a physical line number instead of a record number
+    pub fn is_line_based(&self) -> bool {
+        matches!(
+            self,
+            CsvErrorKind::InvalidLineEnding
+                | CsvErrorKind::MixedLineEnding { .. }
+                | CsvErrorKind::BlankLine
+                | CsvErrorKind::TrailingBlankLine
+                | CsvErrorKind::MissingFinalNewline
+                | CsvErrorKind::UnexpectedFinalNewline
+                | CsvErrorKind::CommentLine
+                | CsvErrorKind::LineLength { .. }
+                | CsvErrorKind::Padding(_)
+                | CsvErrorKind::Alignment(_)
+                | CsvErrorKind::FieldType { .. }
+        )
+    }
+
+    /// Whether this error is about the structure of the file as a whole
+    /// rather than about one record or line
+    pub fn is_file_level(&self) -> bool {
+        matches!(
+            self,
+            CsvErrorKind::BlankLine
+                | CsvErrorKind::TrailingBlankLine
+                | CsvErrorKind::MissingFinalNewline
+                | CsvErrorKind::UnexpectedFinalNewline
+                | CsvErrorKind::UnknownColumn(_)
+                | CsvErrorKind::TooManyRecords { .. }
+                | CsvErrorKind::Io(_)
+        )
+    }
+}
+
 impl std::fmt::Display for CsvError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Record #{} has error: {}", self.record_num, self.error)
+        let unit = if self.error.is_line_based() {
+            "Line"
+        } else {
+            "Record"
+        };
+        write!(f, "{} #{} has error: {}", unit, self.record_num, self.error)
     }
 }
 
@@ -51,6 +339,26 @@ pub struct ValidationResult {
     pub errors: Vec<CsvError>,
     /// Whether parsing was halted due to a fatal error
     pub halted: bool,
+    /// Whether errors were dropped or validation stopped early because of an error limit
+    pub truncated: bool,
+    /// How many lines end in each terminator
+    pub line_endings: LineEndingStats,
+}
+
+impl ValidationResult {
+    pub(crate) fn from_collector(
+        errors: ErrorCollector,
+        halted: bool,
+        line_endings: LineEndingStats,
+    ) -> Self {
+        let (errors, truncated) = errors.into_parts();
+        ValidationResult {
+            errors,
+            halted,
+            truncated,
+            line_endings,
+        }
+    }
 }
 
 /// Validates whether a CSV file conforms to RFC 4180
@@ -67,82 +375,340 @@ pub fn validate<R: Read>(
     delimiter: u8,
     lazy_quotes: bool,
     rfc4180_mode: bool,
+) -> Result<ValidationResult, Box<dyn std::error::Error>> {
+    let options = ValidationOptions::new(delimiter, lazy_quotes, rfc4180_mode);
+    validate_with_options(reader, &options)
+}
+
+/// Validates CSV data that is already in memory
+///
+/// This is the same as [`validate`], but avoids copying the input.
+pub fn validate_slice(
+    content: &[u8],
+    delimiter: u8,
+    lazy_quotes: bool,
+    rfc4180_mode: bool,
+) -> Result<ValidationResult, Box<dyn std::error::Error>> {
+    let options = ValidationOptions::new(delimiter, lazy_quotes, rfc4180_mode);
+    validate_slice_with_options(content, &options)
+}
+
+/// Validates a CSV file using the given options
+///
+/// The whole input is read into memory first, so limits such as
+/// [`SizeLimits::max_record_bytes`] don't bound the memory used; to validate
+/// a large file in place, map it and use [`validate_slice_with_options`].
+pub fn validate_with_options<R: Read>(
+    reader: R,
+    options: &ValidationOptions,
 ) -> Result<ValidationResult, Box<dyn std::error::Error>> {
     // First, read the entire content to check line endings and other RFC 4180 requirements
     let mut content = Vec::new();
     let mut reader = reader;
     reader.read_to_end(&mut content)?;
 
-    let mut errors = Vec::new();
+    validate_slice_with_options(&content, options)
+}
 
-    // Check for proper line endings (RFC 4180 requires CRLF)
-    if rfc4180_mode {
-        validate_line_endings(&content, &mut errors);
+/// Validates CSV data that is already in memory using the given options
+pub fn validate_slice_with_options(
+    content: &[u8],
+    options: &ValidationOptions,
+) -> Result<ValidationResult, Box<dyn std::error::Error>> {
+    if let Some(spec) = &options.fixed_width {
+        return Ok(fixed_width::validate(content, spec, options));
+    }
+
+    // Skipped lines are cut off first; line numbers still count them
+    let data = skip::data(content, options);
+    let content = sampled_content(&content[data.range], options);
+    let mut errors = ErrorCollector::new(&options.limits);
+
+    // Check for proper line endings (CRLF in RFC 4180 mode, and consistent terminators)
+    let line_endings = LineEndingStats::from_content(content, options);
+    let expected = expected_line_ending(&line_endings, options);
+    validate_line_endings(content, data.first_line, options, expected, &mut errors);
+    validate_blank_lines(content, data.first_line, options, &mut errors);
+    validate_comments(content, data.first_line, options, &mut errors);
+    let content = trim_final_comment(content, options);
+
+    // Fast path: with uniform field counts and valid UTF-8, the record loop has nothing to
+    // report unless a rule looks at the fields themselves
+    if !options.has_field_rules()
+        && tokenizer::counts(content, options).is_uniform()
+        && std::str::from_utf8(content).is_ok()
+    {
+        return Ok(ValidationResult::from_collector(
+            errors,
+            false,
+            line_endings,
+        ));
+    }
+
+    // Records over the byte limit are found before parsing, so that they can be skipped
+    let oversized = size_limits::oversized_records(content, options);
+    if !size_limits::validate_header_size(&oversized, options, &mut errors) {
+        return Ok(ValidationResult::from_collector(errors, true, line_endings));
+    }
+
+    if let Some(delimiter) = &options.custom_delimiter {
+        let outcome = validate_tokenized(content, delimiter, &oversized, options, &mut errors);
+        size_limits::validate_record_count(outcome.records, options, &mut errors);
+        return Ok(ValidationResult::from_collector(
+            errors,
+            outcome.halted,
+            line_endings,
+        ));
     }
 
     // Now validate CSV structure using the csv crate
-    let cursor = std::io::Cursor::new(&content);
-    let mut csv_reader = ReaderBuilder::new()
-        .delimiter(delimiter)
+    let mut csv_reader = csv_reader_builder(options).from_reader(content);
+
+    let header = match read_header(&mut csv_reader, options, &mut errors) {
+        Ok(header) => header,
+        Err(()) => {
+            return Ok(ValidationResult::from_collector(errors, true, line_endings));
+        }
+    };
+
+    let outcome = if oversized.is_empty() {
+        validate_records(
+            &mut csv_reader,
+            content,
+            header.as_ref(),
+            0,
+            options,
+            &mut errors,
+        )
+    } else {
+        size_limits::validate_records_skipping(
+            content,
+            record_end(content, csv_reader.position()),
+            &oversized,
+            header.as_ref(),
+            options,
+            &mut errors,
+        )
+    };
+    size_limits::validate_record_count(outcome.records, options, &mut errors);
+
+    Ok(ValidationResult::from_collector(
+        errors,
+        outcome.halted,
+        line_endings,
+    ))
+}
+
+/// Restricts `content` to the rows selected by [`Sample::FirstRows`]
+pub(crate) fn sampled_content<'a>(content: &'a [u8], options: &ValidationOptions) -> &'a [u8] {
+    let Sample::FirstRows(rows) = options.sample else {
+        return content;
+    };
+    if options.custom_delimiter.is_some() {
+        let mut end = None;
+        let mut index = 0;
+        tokenizer::records(content, options, |span| {
+            if index == rows {
+                end = Some(span.end);
+            }
+            index += 1;
+        });
+        // Keep the terminator of the last record
+        return match end {
+            Some(end) if content[end..].starts_with(b"\r\n") => &content[..end + 2],
+            Some(end) if end < content.len() => &content[..end + 1],
+            _ => content,
+        };
+    }
+
+    // Read the header plus `rows` records and cut the input after the last one
+    let mut csv_reader = csv_reader_builder(options).from_reader(content);
+    let mut record = csv::ByteRecord::new();
+    for _ in 0..=rows {
+        match csv_reader.read_byte_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) | Err(_) => return content,
+        }
+    }
+
+    &content[..record_end(content, csv_reader.position())]
+}
+
+/// Cuts off a comment on the last line of `content` if it has no line break
+///
+/// The csv crate reads such a comment as a record with one empty field.
+pub(crate) fn trim_final_comment<'a>(content: &'a [u8], options: &ValidationOptions) -> &'a [u8] {
+    let Some(comment) = options.comment else {
+        return content;
+    };
+    let mut last = None;
+    tokenizer::comments(content, options, comment, |span| last = Some(span));
+    match last {
+        Some(span) if span.end == content.len() => &content[..span.start],
+        _ => content,
+    }
+}
+
+/// Returns the byte offset just past the record that ended at `position`
+///
+/// The csv reader stops between the CR and LF of a CRLF terminator; the LF
+/// is included here so that the offset is a clean line boundary.
+pub(crate) fn record_end(content: &[u8], position: &csv::Position) -> usize {
+    let end = position.byte() as usize;
+    if end > 0 && content[end - 1] == b'\r' && content.get(end) == Some(&b'\n') {
+        end + 1
+    } else {
+        end
+    }
+}
+
+/// Builds a csv reader with the settings shared by all validation paths
+pub(crate) fn csv_reader_builder(options: &ValidationOptions) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+        .delimiter(options.delimiter)
+        .has_headers(false) // The header is read explicitly so it can be validated too
         .flexible(true) // Allow variable number of fields per record for validation
-        .quoting(!lazy_quotes) // Disable strict quoting if lazy_quotes is true
-        .from_reader(cursor);
+        .quoting(!options.lazy_quotes) // Disable strict quoting if lazy_quotes is true
+        .comment(options.comment);
+    builder
+}
 
-    let mut record_num = 0;
-    let mut header_len: Option<usize> = None;
+/// Reads and validates the header record
+///
+/// Returns `Err(())` when the header could not be parsed; the error is pushed onto `errors`.
+pub(crate) fn read_header<R: Read>(
+    csv_reader: &mut csv::Reader<R>,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) -> Result<Option<StringRecord>, ()> {
     let mut string_record = StringRecord::new();
-
-    // Read header first
     match csv_reader.read_record(&mut string_record) {
-        Ok(has_record) => {
-            if has_record {
-                header_len = Some(string_record.len());
-                // Validate header doesn't end with comma (trailing comma)
-                if !lazy_quotes {
-                    validate_record_format(&string_record, 0, &mut errors);
-                }
-            }
+        Ok(true) => {
+            validate_header(&string_record, options, errors);
+            Ok(Some(string_record))
         }
+        Ok(false) => Ok(None),
         Err(csv_error) => {
             errors.push(CsvError {
                 record: None,
                 record_num: 0,
                 error: convert_csv_error(&csv_error),
             });
-            return Ok(ValidationResult {
-                errors,
-                halted: true,
+            Err(())
+        }
+    }
+}
+
+/// Checks the header record
+fn validate_header(
+    header: &StringRecord,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) {
+    // Validate header doesn't end with comma (trailing comma)
+    if !options.lazy_quotes {
+        validate_record_format(header, 0, errors);
+    }
+    validate_key_columns(header, options, errors);
+}
+
+/// Reports the columns named by the key options that are missing from the header
+///
+/// Done once here rather than by the checkers, which run once per chunk in parallel mode.
+fn validate_key_columns(
+    header: &StringRecord,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) {
+    let names = options
+        .unique_key
+        .iter()
+        .chain(options.foreign_keys.iter().flat_map(|check| &check.columns))
+        .chain(options.size_limits.columns());
+    let mut reported: Vec<&String> = Vec::new();
+    for name in names {
+        if !header.iter().any(|column| column == name) && !reported.contains(&name) {
+            reported.push(name);
+            errors.push(CsvError {
+                record: None,
+                record_num: 0,
+                error: CsvErrorKind::UnknownColumn(name.clone()),
             });
         }
     }
+}
+
+/// Outcome of running the record loop over (part of) a file
+#[derive(Debug, Default)]
+pub(crate) struct RecordsOutcome {
+    /// Number of data records successfully read
+    pub records: usize,
+    /// Whether a parse error stopped the record loop
+    pub stopped: bool,
+    /// Whether the parse error was fatal
+    pub halted: bool,
+}
+
+/// Validates data records until the end of input, the first parse error, or the error limit
+///
+/// `content` is the input of `csv_reader`, indexed by its byte positions, and
+/// `first_record_num` is the number of data records that precede this reader's input.
+pub(crate) fn validate_records<R: Read>(
+    csv_reader: &mut csv::Reader<R>,
+    content: &[u8],
+    header: Option<&StringRecord>,
+    first_record_num: usize,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) -> RecordsOutcome {
+    let mut record_num = first_record_num;
+    let mut string_record = StringRecord::new();
+    let mut checks = RecordChecks::new(header, options);
 
-    // Read remaining records
     loop {
+        if errors.is_full() {
+            errors.set_truncated();
+            break;
+        }
+
+        let record_start = csv_reader.position().byte() as usize;
         match csv_reader.read_record(&mut string_record) {
             Ok(has_record) => {
                 if !has_record {
-                    break; // End of file
+                    break; // End of input
                 }
 
                 record_num += 1;
 
-                // Validate record format (quotes, escaping, etc.)
-                if !lazy_quotes {
-                    validate_record_format(&string_record, record_num + 1, &mut errors);
+                if !options.sample.includes(record_num) {
+                    continue;
                 }
 
-                // Check field count consistency
-                if let Some(expected_len) = header_len {
-                    if string_record.len() != expected_len {
-                        errors.push(CsvError {
-                            record: Some(string_record.iter().map(|s| s.to_string()).collect()),
-                            record_num: record_num + 1, // +1 because we want to report 1-indexed record numbers including the header
-                            error: CsvErrorKind::FieldCount,
-                        });
-                    }
-                }
+                let record_end = csv_reader.position().byte() as usize;
+                let quoted = || {
+                    quoted_fields(
+                        &content[record_start..record_end],
+                        options.delimiter,
+                        !options.lazy_quotes,
+                        options.comment,
+                    )
+                };
+                checks.check(&string_record, quoted, record_num, errors);
             }
             Err(csv_error) => {
+                // A record whose only invalid UTF-8 is lone surrogates is reported as such
+                if options.characters && matches!(csv_error.kind(), csv::ErrorKind::Utf8 { .. }) {
+                    let record_end = csv_reader.position().byte() as usize;
+                    let raw = &content[record_start..record_end];
+                    if !options.sample.includes(record_num + 1)
+                        || validate_surrogates(raw, header, record_num + 1, options, errors)
+                    {
+                        record_num += 1;
+                        continue;
+                    }
+                }
+
                 // Convert csv::Error to our error types
                 let error_kind = convert_csv_error(&csv_error);
 
@@ -158,50 +724,206 @@ pub fn validate<R: Read>(
                     csv::ErrorKind::Io(_) | csv::ErrorKind::Utf8 { .. }
                 );
 
-                return Ok(ValidationResult { errors, halted });
+                return RecordsOutcome {
+                    records: record_num - first_record_num,
+                    stopped: true,
+                    halted,
+                };
             }
         }
     }
 
-    Ok(ValidationResult {
-        errors,
+    RecordsOutcome {
+        records: record_num - first_record_num,
+        stopped: false,
         halted: false,
-    })
+    }
 }
 
-/// Validates line endings according to RFC 4180 (requires CRLF)
-fn validate_line_endings(content: &[u8], errors: &mut Vec<CsvError>) {
-    let mut line_num = 1;
-    let mut i = 0;
+/// Validates the header and data records of `content`, read by the [`tokenizer`]
+///
+/// Records over the byte limit are reported instead of parsed. The tokenizer
+/// reads anything, so only invalid UTF-8 stops validation.
+fn validate_tokenized(
+    content: &[u8],
+    delimiter: &tokenizer::Delimiter,
+    oversized: &[size_limits::OversizedRecord],
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) -> RecordsOutcome {
+    let mut reader = tokenizer::Reader::new(content, delimiter, options);
+    let mut byte_record = csv::ByteRecord::new();
+    let halt = |records: usize| RecordsOutcome {
+        records,
+        stopped: true,
+        halted: true,
+    };
 
-    while i < content.len() {
-        if content[i] == b'\n' {
-            // Found LF, check if it's preceded by CR
-            if i == 0 || content[i - 1] != b'\r' {
+    let header = match reader.read_byte_record(&mut byte_record) {
+        Some(spans) => match StringRecord::from_byte_record(byte_record.clone()) {
+            Ok(header) => {
+                validate_header(&header, options, errors);
+                Some(header)
+            }
+            Err(e) => {
                 errors.push(CsvError {
                     record: None,
-                    record_num: line_num,
-                    error: CsvErrorKind::InvalidLineEnding,
+                    record_num: 0,
+                    error: CsvErrorKind::Utf8(
+                        reader.utf8_error(&spans, e.utf8_error()).to_string(),
+                    ),
                 });
+                return halt(0);
             }
-            line_num += 1;
-        } else if content[i] == b'\r' {
-            // Found CR, check if it's followed by LF
-            if i + 1 >= content.len() || content[i + 1] != b'\n' {
+        },
+        None => None,
+    };
+
+    let header = header.as_ref();
+    let mut checks = RecordChecks::new(header, options);
+    let mut oversized = oversized.iter().peekable();
+    let mut records = 0;
+    loop {
+        if errors.is_full() {
+            errors.set_truncated();
+            break;
+        }
+        let Some(spans) = reader.read_byte_record(&mut byte_record) else {
+            break;
+        };
+        let record_num = spans.index;
+        if let Some(record) = oversized.next_if(|record| record.index == record_num) {
+            if options.sample.includes(record_num) {
+                errors.push(size_limits::record_too_long(record, options));
+            }
+            records = record_num;
+            continue;
+        }
+
+        match StringRecord::from_byte_record(byte_record.clone()) {
+            Ok(string_record) => {
+                records = record_num;
+                if !options.sample.includes(record_num) {
+                    continue;
+                }
+                let quoted = || {
+                    let quoting = !options.lazy_quotes;
+                    spans
+                        .fields
+                        .iter()
+                        .map(|field| quoting && content[field.start] == b'"')
+                        .collect()
+                };
+                checks.check(&string_record, quoted, record_num, errors);
+            }
+            Err(e) => {
+                // A record whose only invalid UTF-8 is lone surrogates is reported as such
+                if options.characters
+                    && (!options.sample.includes(record_num)
+                        || validate_record_surrogates(
+                            &byte_record,
+                            header,
+                            record_num,
+                            options,
+                            errors,
+                        ))
+                {
+                    records = record_num;
+                    continue;
+                }
                 errors.push(CsvError {
                     record: None,
-                    record_num: line_num,
-                    error: CsvErrorKind::InvalidLineEnding,
+                    record_num,
+                    error: CsvErrorKind::Utf8(
+                        reader.utf8_error(&spans, e.utf8_error()).to_string(),
+                    ),
                 });
+                return halt(records);
             }
         }
-        i += 1;
+    }
+
+    RecordsOutcome {
+        records,
+        stopped: false,
+        halted: false,
+    }
+}
+
+/// The rules checked on each data record, however it was read
+pub(crate) struct RecordChecks<'a> {
+    header: Option<&'a StringRecord>,
+    options: &'a ValidationOptions,
+    check_whitespace: bool,
+    unique: Option<UniqueChecker>,
+    foreign_keys: ForeignKeyChecker<'a>,
+    field_limits: Option<FieldLimits>,
+}
+
+impl<'a> RecordChecks<'a> {
+    pub fn new(header: Option<&'a StringRecord>, options: &'a ValidationOptions) -> Self {
+        RecordChecks {
+            header,
+            options,
+            check_whitespace: whitespace::enabled(options),
+            unique: UniqueChecker::new(options, header),
+            foreign_keys: ForeignKeyChecker::new(options, header),
+            field_limits: FieldLimits::new(options, header),
+        }
+    }
+
+    /// Checks one data record; `quoted` tells which of its fields were quoted
+    pub fn check(
+        &mut self,
+        record: &StringRecord,
+        quoted: impl FnOnce() -> Vec<bool>,
+        record_num: usize,
+        errors: &mut ErrorCollector,
+    ) {
+        let (header, options) = (self.header, self.options);
+
+        // Validate record format (quotes, escaping, etc.)
+        if !options.lazy_quotes {
+            validate_record_format(record, record_num, errors);
+        }
+
+        // Check field count consistency
+        if let Some(header) = header
+            && record.len() != header.len()
+        {
+            errors.push(CsvError {
+                record: Some(record.iter().map(|s| s.to_string()).collect()),
+                record_num,
+                error: CsvErrorKind::FieldCount,
+            });
+        }
+
+        if self.check_whitespace {
+            validate_whitespace(&quoted(), record, header, record_num, options, errors);
+        }
+        if options.formula_injection {
+            validate_formulas(record, header, record_num, errors);
+        }
+        if options.characters {
+            validate_characters(record, header, record_num, options, errors);
+        }
+        if let Some(unique) = &mut self.unique {
+            unique.check(record, record_num, errors);
+        }
+        self.foreign_keys.check(record, record_num, errors);
+        if let Some(field_limits) = &self.field_limits {
+            field_limits.check(record, header, record_num, errors);
+        }
     }
 }
 
 /// Validates individual record format according to RFC 4180
 /// Note: This validates the raw CSV content, not parsed fields
-fn validate_record_format(_record: &StringRecord, _record_num: usize, _errors: &mut [CsvError]) {
+pub(crate) fn validate_record_format(
+    _record: &StringRecord,
+    _record_num: usize,
+    _errors: &mut ErrorCollector,
+) {
     // For now, we'll rely on the CSV parser's built-in validation
     // since it already handles quote escaping and field parsing correctly.
     // Additional validation could be added here for specific RFC 4180 requirements
@@ -213,7 +935,7 @@ fn validate_record_format(_record: &StringRecord, _record_num: usize, _errors: &
 }
 
 /// Converts csv crate errors to our error types
-fn convert_csv_error(csv_error: &csv::Error) -> CsvErrorKind {
+pub(crate) fn convert_csv_error(csv_error: &csv::Error) -> CsvErrorKind {
     match csv_error.kind() {
         csv::ErrorKind::UnequalLengths { .. } => CsvErrorKind::FieldCount,
         csv::ErrorKind::Utf8 { .. } => CsvErrorKind::Utf8(csv_error.to_string()),
@@ -281,6 +1003,244 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_mixed_line_endings() {
+        let csv_data = "a,b\r\n1,2\n3,4\r\n5,6\r\n";
+        let result = validate(Cursor::new(csv_data), b',', false, false).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].record_num, 2);
+        assert_eq!(
+            result.errors[0].error,
+            CsvErrorKind::MixedLineEnding {
+                expected: LineEnding::CrLf,
+                found: LineEnding::Lf
+            }
+        );
+        assert_eq!(result.line_endings.dominant(), Some(LineEnding::CrLf));
+
+        // RFC 4180 mode reports the same line as an invalid line ending instead
+        let result = validate(Cursor::new(csv_data), b',', false, true).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].error, CsvErrorKind::InvalidLineEnding);
+
+        let options = ValidationOptions {
+            mixed_line_endings: false,
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(csv_data.as_bytes(), &options).unwrap();
+        assert!(result.errors.is_empty());
+    }
+
+    #[test]
+    fn test_formula_injection() {
+        let csv_data = "id,amount,note\n1,-42,ok\n2,10,=1+1\n";
+        let options = ValidationOptions {
+            formula_injection: true,
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(csv_data.as_bytes(), &options).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].record_num, 2);
+        assert_eq!(
+            result.errors[0].error.to_string(),
+            "possible formula injection in column 3 (\"note\")"
+        );
+    }
+
+    #[test]
+    fn test_character_rules() {
+        let csv_data = b"id,name\n1,a\xed\xb0\x80\n2,b\x00\n3,c\xff\n4,d\n";
+        let options = ValidationOptions {
+            characters: true,
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(csv_data, &options).unwrap();
+        let kinds: Vec<_> = result
+            .errors
+            .iter()
+            .map(|e| (e.record_num, e.error.name()))
+            .collect();
+        // Lone surrogates don't stop validation, other invalid UTF-8 still does
+        assert_eq!(
+            kinds,
+            vec![(1, "LoneSurrogate"), (2, "NulByte"), (3, "Utf8")]
+        );
+        assert!(result.halted);
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let csv_data = "id,name\n1,ab\n2,\"été\"\n3,x,y\n4,\"unterminated\n5,z\n";
+        let limit = |column: Option<&str>, max, unit| FieldLengthLimit {
+            column: column.map(str::to_string),
+            max,
+            unit,
+        };
+        let options = ValidationOptions {
+            size_limits: SizeLimits {
+                field_lengths: vec![
+                    limit(None, 1, LengthUnit::Bytes),
+                    limit(Some("name"), 4, LengthUnit::Bytes),
+                    limit(Some("name"), 2, LengthUnit::Chars),
+                ],
+                max_fields: Some(2),
+                max_record_bytes: Some(10),
+                max_records: Some(2),
+            },
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(csv_data.as_bytes(), &options).unwrap();
+        let errors: Vec<String> = result.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "Record #2 has error: column 2 (\"name\") is 5 bytes long, limit is 4",
+                "Record #3 has error: wrong number of fields",
+                "Record #3 has error: record has 3 fields, limit is 2",
+                "Record #4 has error: record is 20 bytes long, limit is 10; it was not parsed",
+                "Record #3 has error: file has 4 records, limit is 2",
+            ]
+        );
+        assert!(!result.halted);
+    }
+
+    #[test]
+    fn test_unique_rows_and_keys() {
+        let csv_data = "id,name\n1,a\n2,b\n1,a\n2,c\n";
+        let options = ValidationOptions {
+            unique_rows: true,
+            unique_key: vec!["id".to_string()],
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(csv_data.as_bytes(), &options).unwrap();
+        let errors: Vec<String> = result.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "Record #3 has error: duplicate row, first seen in record 1",
+                "Record #3 has error: duplicate key [\"1\"], first seen in record 1",
+                "Record #4 has error: duplicate key [\"2\"], first seen in record 2",
+            ]
+        );
+
+        let options = ValidationOptions {
+            unique_key: vec!["id".to_string(), "missing".to_string()],
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(csv_data.as_bytes(), &options).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(
+            result.errors[0].error,
+            CsvErrorKind::UnknownColumn("missing".to_string())
+        );
+    }
+
+    #[test]
+    fn test_comment_lines() {
+        let content =
+            b"# source: \"survey\r\n# units: m,s\r\nx,t\r\n1,2\r\n# dropped\r\n3\r\n4,5\r\n# end";
+        let mut options = ValidationOptions {
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(content, &options).unwrap();
+        let reported: Vec<String> = result.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            reported,
+            vec!["Record #2 has error: wrong number of fields"]
+        );
+        assert_eq!(result.errors[0].record, Some(vec!["3".to_string()]));
+
+        // Strict mode reports each comment line by its physical line number
+        options.rfc4180_mode = true;
+        let result = validate_slice_with_options(content, &options).unwrap();
+        let comments: Vec<usize> = result
+            .errors
+            .iter()
+            .filter(|e| e.error == CsvErrorKind::CommentLine)
+            .map(|e| e.record_num)
+            .collect();
+        assert_eq!(comments, vec![1, 2, 5, 8]);
+
+        // Without the option, comments are records and the first is the header
+        let result = validate_slice_with_options(content, &ValidationOptions::default()).unwrap();
+        assert_eq!(result.errors.len(), 4);
+    }
+
+    #[test]
+    fn test_custom_delimiter() {
+        let content = "id~|~name~|~note\n1~|~\" Ann\"~|~\"a~|~b\"\n2~|~Bob\n\n3~|~=1+1~|~x\n";
+        let options = ValidationOptions {
+            custom_delimiter: Some(tokenizer::Delimiter::Literal(b"~|~".to_vec())),
+            surrounding_whitespace: FieldScope::Quoted,
+            formula_injection: true,
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(content.as_bytes(), &options).unwrap();
+        let reported: Vec<String> = result.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            reported,
+            vec![
+                "Record #1 has error: leading whitespace in column 2 (\"name\")",
+                "Record #2 has error: wrong number of fields",
+                "Record #3 has error: possible formula injection in column 2 (\"name\")",
+            ]
+        );
+        assert_eq!(
+            result.errors[1].record,
+            Some(vec!["2".to_string(), "Bob".to_string()])
+        );
+
+        // Unicode delimiters, and invalid UTF-8 reported the same way as by the csv crate
+        let options = ValidationOptions {
+            custom_delimiter: Some(tokenizer::Delimiter::Literal("¦".as_bytes().to_vec())),
+            ..Default::default()
+        };
+        let result = validate_slice_with_options("a¦b\n1¦2\n".as_bytes(), &options).unwrap();
+        assert!(result.errors.is_empty());
+        let result = validate_slice_with_options(b"a\xc2\xa6b\n1\xc2\xa6\xff\n", &options).unwrap();
+        assert!(result.halted);
+        assert_eq!(
+            result.errors[0].error,
+            CsvErrorKind::Utf8(
+                "CSV parse error: record 1 (line 2, field: 1, byte: 5): \
+                 invalid utf-8: invalid UTF-8 in field 1 near byte index 0"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_skip_lines() {
+        let content = b"Report, \"Q3\n\nid,name\n1, a\n2,b\r\n3\nTotal:,3\n";
+        let options = ValidationOptions {
+            skip: skip::SkipLines {
+                preamble: 2,
+                footer: 0,
+                footer_pattern: Some(regex::bytes::Regex::new("^Total:").unwrap()),
+            },
+            surrounding_whitespace: FieldScope::All,
+            ..Default::default()
+        };
+        let result = validate_slice_with_options(content, &options).unwrap();
+        let reported: Vec<String> = result.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            reported,
+            vec![
+                "Line #5 has error: mixed line endings: found CRLF, file predominantly uses LF",
+                "Record #1 has error: leading whitespace in column 2 (\"name\")",
+                "Record #3 has error: wrong number of fields",
+            ]
+        );
+
+        // Fixes leave the skipped lines alone
+        let fixed = fix::fix_slice(content, &options);
+        assert_eq!(
+            fixed.content,
+            b"Report, \"Q3\n\nid,name\n1,a\n2,b\r\n3\nTotal:,3\n"
+        );
+    }
+
     #[test]
     fn test_lazy_quotes_allows_lf() {
         let csv_data = "field1,field2,field3\na,b,c\nd,e,f\n"; // LF only
@@ -347,6 +1307,24 @@ mod tests {
         assert!(result.errors.is_empty());
     }
 
+    #[test]
+    fn test_error_limits_and_sampling() {
+        let csv_data = "a,b\n1,2,3\n4,5\n6\n7,8,9\n";
+
+        let mut options = ValidationOptions::new(b',', false, true);
+        options.limits.max_errors = Some(2);
+        let result = validate_slice_with_options(csv_data.as_bytes(), &options).unwrap();
+        assert_eq!(result.errors.len(), 2);
+        assert!(result.truncated);
+
+        let mut options = ValidationOptions::new(b',', false, false);
+        options.sample = Sample::FirstRows(2);
+        let result = validate_slice_with_options(csv_data.as_bytes(), &options).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].record_num, 1);
+        assert!(!result.truncated);
+    }
+
     // Integration tests using actual test data files
     struct TestCase {
         file: &'static str,
@@ -484,5 +1462,12 @@ mod tests {
             error.to_string(),
             "Record #1 has error: bare \" in non-quoted-field"
         );
+
+        let error = CsvError {
+            record: None,
+            record_num: 4,
+            error: CsvErrorKind::BlankLine,
+        };
+        assert_eq!(error.to_string(), "Line #4 has error: blank line");
     }
 }
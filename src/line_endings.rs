@@ -0,0 +1,341 @@
+//! Line ending rules
+//!
+//! Two rules are checked here: RFC 4180 requires every line to end in CRLF,
+//! and in every mode each line is expected to use the file's dominant
+//! terminator, so that a few stray LF lines in a CRLF file are caught. Line
+//! breaks inside quoted fields are part of a value, and only checked by the
+//! RFC 4180 rule.
+
+use crate::collector::ErrorCollector;
+use crate::{CsvError, CsvErrorKind, ValidationOptions, structural, tokenizer};
+use std::fmt;
+use std::ops::Range;
+
+/// A line terminator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    CrLf,
+    Lf,
+    Cr,
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineEnding::CrLf => write!(f, "CRLF"),
+            LineEnding::Lf => write!(f, "LF"),
+            LineEnding::Cr => write!(f, "CR"),
+        }
+    }
+}
+
+impl LineEnding {
+    /// The terminator's bytes
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Lf => "\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// Replaces every terminator in `content` with `ending`, including those inside quoted fields
+pub fn normalize_line_endings(content: &[u8], ending: LineEnding) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(content.len());
+    let mut copied = 0;
+    structural::scan_line_breaks(content, |i, byte| {
+        // The LF of a CRLF was handled with its CR
+        if byte == b'\n' && i > 0 && content[i - 1] == b'\r' {
+            return;
+        }
+        let len = if byte == b'\r' && content.get(i + 1) == Some(&b'\n') {
+            2
+        } else {
+            1
+        };
+        normalized.extend_from_slice(&content[copied..i]);
+        normalized.extend_from_slice(ending.as_str().as_bytes());
+        copied = i + len;
+    });
+    normalized.extend_from_slice(&content[copied..]);
+    normalized
+}
+
+/// Number of lines ending in each terminator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineEndingStats {
+    pub crlf: usize,
+    pub lf: usize,
+    pub cr: usize,
+}
+
+impl LineEndingStats {
+    /// Counts the terminators in `content`, read according to `options`
+    ///
+    /// Line breaks inside quoted fields are part of a value, and not counted,
+    /// unless RFC 4180 mode or a fixed-width spec makes every one count.
+    pub fn from_content(content: &[u8], options: &ValidationOptions) -> Self {
+        let mut stats = LineEndingStats::default();
+        for_each_line_ending(content, 1, options, |_, ending| stats.add(ending));
+        stats
+    }
+
+    fn add(&mut self, ending: LineEnding) {
+        match ending {
+            LineEnding::CrLf => self.crlf += 1,
+            LineEnding::Lf => self.lf += 1,
+            LineEnding::Cr => self.cr += 1,
+        }
+    }
+
+    /// Total number of terminated lines
+    pub fn total(&self) -> usize {
+        self.crlf + self.lf + self.cr
+    }
+
+    /// The most common terminator; ties prefer CRLF, then LF
+    pub fn dominant(&self) -> Option<LineEnding> {
+        if self.total() == 0 {
+            None
+        } else if self.crlf >= self.lf && self.crlf >= self.cr {
+            Some(LineEnding::CrLf)
+        } else if self.lf >= self.cr {
+            Some(LineEnding::Lf)
+        } else {
+            Some(LineEnding::Cr)
+        }
+    }
+
+    /// Whether more than one kind of terminator is used
+    pub fn is_mixed(&self) -> bool {
+        [self.crlf, self.lf, self.cr]
+            .iter()
+            .filter(|&&n| n > 0)
+            .count()
+            > 1
+    }
+}
+
+/// Calls `visit` with the byte range, without its terminator, and the terminator of each line in `content`
+///
+/// CR, LF and CRLF each end one line, and a line break at the end of
+/// `content` doesn't start another line; the last line has no terminator
+/// otherwise. Every rule numbering physical lines counts them this way.
+pub(crate) fn for_each_line(
+    content: &[u8],
+    mut visit: impl FnMut(Range<usize>, Option<LineEnding>),
+) {
+    let mut start = 0;
+    structural::scan_line_breaks(content, |i, byte| {
+        let (end, ending) = if byte == b'\r' {
+            if content.get(i + 1) == Some(&b'\n') {
+                // The LF of the CRLF ends the line
+                return;
+            }
+            (i, LineEnding::Cr)
+        } else if i > 0 && content[i - 1] == b'\r' {
+            (i - 1, LineEnding::CrLf)
+        } else {
+            (i, LineEnding::Lf)
+        };
+        visit(start..end, Some(ending));
+        start = i + 1;
+    });
+    if start < content.len() {
+        visit(start..content.len(), None);
+    }
+}
+
+/// Number of line breaks in `content`, counting CRLF once
+pub(crate) fn count_line_breaks(content: &[u8]) -> usize {
+    let mut count = 0;
+    for_each_line(content, |_, ending| count += usize::from(ending.is_some()));
+    count
+}
+
+/// Number of the line containing byte `offset` of `content`, counting from 1
+pub(crate) fn line_number(content: &[u8], offset: usize) -> usize {
+    1 + count_line_breaks(&content[..offset])
+}
+
+/// Calls `visit` with the line number and terminator of each terminated line in `content`
+///
+/// Only records are terminated by the line breaks outside quoted fields;
+/// those inside are skipped, but still counted in line numbers. RFC 4180
+/// mode, which requires every line break to be CRLF, and fixed-width files,
+/// which have no quotes, visit every line break.
+fn for_each_line_ending(
+    content: &[u8],
+    first_line: usize,
+    options: &ValidationOptions,
+    mut visit: impl FnMut(usize, LineEnding),
+) {
+    let mut line_num = first_line;
+    if options.rfc4180_mode || options.fixed_width.is_some() {
+        for_each_line(content, |_, ending| {
+            if let Some(ending) = ending {
+                visit(line_num, ending);
+                line_num += 1;
+            }
+        });
+        return;
+    }
+
+    let mut counted = 0;
+    tokenizer::line_breaks(content, options, |i, byte| {
+        let ending = if byte == b'\r' {
+            if content.get(i + 1) == Some(&b'\n') {
+                // The LF of the CRLF ends the line
+                return;
+            }
+            LineEnding::Cr
+        } else if i > 0 && content[i - 1] == b'\r' {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        };
+        let start = if ending == LineEnding::CrLf { i - 1 } else { i };
+        line_num += count_line_breaks(&content[counted..start]);
+        visit(line_num, ending);
+        line_num += 1;
+        counted = i + 1;
+    });
+}
+
+/// The terminator every line is checked against by the mixed line ending rule, if any
+pub(crate) fn expected_line_ending(
+    stats: &LineEndingStats,
+    options: &ValidationOptions,
+) -> Option<LineEnding> {
+    // In RFC 4180 mode every non-CRLF terminator is already an InvalidLineEnding
+    if options.mixed_line_endings && !options.rfc4180_mode && stats.is_mixed() {
+        stats.dominant()
+    } else {
+        None
+    }
+}
+
+/// Validates line endings: CRLF in RFC 4180 mode, and consistency with `expected`
+///
+/// `first_line` is the line number of the first line in `content`.
+pub(crate) fn validate_line_endings(
+    content: &[u8],
+    first_line: usize,
+    options: &ValidationOptions,
+    expected: Option<LineEnding>,
+    errors: &mut ErrorCollector,
+) {
+    if !options.rfc4180_mode && expected.is_none() {
+        return;
+    }
+
+    for_each_line_ending(content, first_line, options, |line_num, ending| {
+        if !options.sample.includes(line_num) {
+            return;
+        }
+
+        if options.rfc4180_mode && ending != LineEnding::CrLf {
+            errors.push(CsvError {
+                record: None,
+                record_num: line_num,
+                error: CsvErrorKind::InvalidLineEnding,
+            });
+        } else if let Some(expected) = expected
+            && ending != expected
+        {
+            errors.push(CsvError {
+                record: None,
+                record_num: line_num,
+                error: CsvErrorKind::MixedLineEnding {
+                    expected,
+                    found: ending,
+                },
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_ending_stats() {
+        let options = ValidationOptions::default();
+        let stats = LineEndingStats::from_content(b"a\r\nb\r\nc\nd\re\r\n", &options);
+        assert_eq!(
+            stats,
+            LineEndingStats {
+                crlf: 3,
+                lf: 1,
+                cr: 1
+            }
+        );
+        assert_eq!(stats.dominant(), Some(LineEnding::CrLf));
+        assert!(stats.is_mixed());
+
+        let stats = LineEndingStats::from_content(b"a\nb\n", &options);
+        assert_eq!(stats.dominant(), Some(LineEnding::Lf));
+        assert!(!stats.is_mixed());
+        assert_eq!(
+            LineEndingStats::from_content(b"abc", &options).dominant(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mixed_line_endings_in_cr_file() {
+        let content = b"a,b\rc,d\re,f\ng,h\r";
+        let options = ValidationOptions::default();
+        let stats = LineEndingStats::from_content(content, &options);
+        let limits = crate::ErrorLimits::default();
+        let mut errors = ErrorCollector::new(&limits);
+        let expected = expected_line_ending(&stats, &options);
+        validate_line_endings(content, 1, &options, expected, &mut errors);
+        let (errors, _) = errors.into_parts();
+        let reported: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            reported,
+            vec!["Line #3 has error: mixed line endings: found LF, file predominantly uses CR"]
+        );
+    }
+
+    #[test]
+    fn test_line_breaks_in_quoted_fields() {
+        let content = b"id,note\r\n1,\"line one\nline two\"\r\n2,plain\r\n3,x\n";
+        let options = ValidationOptions::default();
+        let stats = LineEndingStats::from_content(content, &options);
+        assert_eq!(
+            stats,
+            LineEndingStats {
+                crlf: 3,
+                lf: 1,
+                cr: 0
+            }
+        );
+        let limits = crate::ErrorLimits::default();
+        let mut errors = ErrorCollector::new(&limits);
+        let expected = expected_line_ending(&stats, &options);
+        validate_line_endings(content, 1, &options, expected, &mut errors);
+        let (errors, _) = errors.into_parts();
+        let reported: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        // The LF inside the quoted field is part of the value, but still counts as a line
+        assert_eq!(
+            reported,
+            vec!["Line #5 has error: mixed line endings: found LF, file predominantly uses CRLF"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_line_endings() {
+        assert_eq!(
+            normalize_line_endings(b"a\r\nb\nc\rd\r\n", LineEnding::Lf),
+            b"a\nb\nc\nd\n"
+        );
+        assert_eq!(
+            normalize_line_endings(b"a\nb\r", LineEnding::CrLf),
+            b"a\r\nb\r\n"
+        );
+    }
+}
@@ -0,0 +1,730 @@
+//! Language server for editor diagnostics
+//!
+//! `csvlint lsp` speaks the Language Server Protocol over stdio. Open
+//! documents are validated on open and on every change, and their errors are
+//! published as diagnostics. Code actions fix what [`crate::fix`] can fix,
+//! normalize line endings and escape stray quotes; hovering a field shows its
+//! column.
+
+use crate::fix::fix_edits;
+use crate::line_endings::{LineEnding, LineEndingStats, for_each_line, normalize_line_endings};
+use crate::tokenizer::{self, first_record};
+use crate::{Column, CsvError, CsvErrorKind, ValidationOptions, skip, validate_slice_with_options};
+use csv::StringRecord;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{CodeActionRequest, HoverRequest, Request as _};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, MarkupContent, MarkupKind, NumberOrString, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Uri, WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Runs the server on stdin and stdout until the client shuts it down
+pub fn serve(options: &ValidationOptions) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (connection, io_threads) = Connection::stdio();
+    serve_connection(&connection, options)?;
+    // The writer thread exits once the connection's sender is dropped
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Runs the server on an established connection until the client shuts it down
+pub fn serve_connection(
+    connection: &Connection,
+    options: &ValidationOptions,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    // Keyed by URI string, since `Uri` caches parts of itself
+    let mut documents: HashMap<String, Document> = HashMap::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = handle_request(request, &documents);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(uri) = handle_notification(notification, &mut documents, options)? {
+                    let (diagnostics, version) = match documents.get(uri.as_str()) {
+                        Some(document) => (document.diagnostics(), Some(document.version)),
+                        None => (Vec::new(), None),
+                    };
+                    let params = PublishDiagnosticsParams {
+                        uri,
+                        diagnostics,
+                        version,
+                    };
+                    connection
+                        .sender
+                        .send(Message::Notification(Notification::new(
+                            PublishDiagnostics::METHOD.to_string(),
+                            params,
+                        )))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Updates the open documents, returning the document whose diagnostics changed
+fn handle_notification(
+    notification: Notification,
+    documents: &mut HashMap<String, Document>,
+    options: &ValidationOptions,
+) -> Result<Option<Uri>, Box<dyn Error + Send + Sync>> {
+    match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+            let document = params.text_document;
+            documents.insert(
+                document.uri.as_str().to_string(),
+                Document::new(document.text, document.version, options),
+            );
+            Ok(Some(document.uri))
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+            // With full sync, the last change holds the whole document
+            let Some(change) = params.content_changes.into_iter().last() else {
+                return Ok(None);
+            };
+            let document = params.text_document;
+            documents.insert(
+                document.uri.as_str().to_string(),
+                Document::new(change.text, document.version, options),
+            );
+            Ok(Some(document.uri))
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+            documents.remove(params.text_document.uri.as_str());
+            Ok(Some(params.text_document.uri))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn handle_request(request: Request, documents: &HashMap<String, Document>) -> Response {
+    let result = match request.method.as_str() {
+        HoverRequest::METHOD => {
+            serde_json::from_value::<HoverParams>(request.params).map(|params| {
+                let position = params.text_document_position_params;
+                let hover = documents
+                    .get(position.text_document.uri.as_str())
+                    .and_then(|document| document.hover(position.position));
+                serde_json::to_value(hover)
+            })
+        }
+        CodeActionRequest::METHOD => serde_json::from_value::<CodeActionParams>(request.params)
+            .map(|params| {
+                let uri = params.text_document.uri;
+                let actions = documents
+                    .get(uri.as_str())
+                    .map(|document| document.code_actions(&uri, params.range))
+                    .unwrap_or_default();
+                serde_json::to_value(actions)
+            }),
+        _ => {
+            return Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request {}", request.method),
+            );
+        }
+    };
+
+    match result {
+        Ok(Ok(value)) => Response::new_ok(request.id, value),
+        Ok(Err(e)) | Err(e) => {
+            Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string())
+        }
+    }
+}
+
+/// An open document and what was found in it
+struct Document {
+    text: String,
+    version: i32,
+    lines: LineIndex,
+    /// Byte ranges of the records, the header first
+    records: Vec<std::ops::Range<usize>>,
+    header: Option<StringRecord>,
+    /// Errors with their byte ranges
+    errors: Vec<(CsvError, std::ops::Range<usize>)>,
+    /// Edits fixing whitespace and formula errors
+    edits: Vec<crate::fix::Edit>,
+    /// The terminator to normalize line endings to, if they are inconsistent
+    line_ending: Option<LineEnding>,
+    options: ValidationOptions,
+}
+
+impl Document {
+    fn new(text: String, version: i32, options: &ValidationOptions) -> Self {
+        let content = text.as_bytes();
+        let data = skip::data(content, options).range;
+        let mut records = Vec::new();
+        tokenizer::records(&content[data.clone()], options, |span| {
+            records.push(data.start + span.start..data.start + span.end)
+        });
+        let header = records
+            .first()
+            .and_then(|span| first_record(&content[span.clone()], options));
+
+        let lines = LineIndex::new(&text);
+        let errors = match validate_slice_with_options(content, options) {
+            Ok(result) => result.errors,
+            Err(_) => Vec::new(),
+        };
+        let errors = errors
+            .into_iter()
+            .map(|error| {
+                let range = error_range(&error, content, &records, options);
+                (error, range)
+            })
+            .collect();
+
+        let stats = LineEndingStats::from_content(content, options);
+        let line_ending = if options.rfc4180_mode {
+            Some(LineEnding::CrLf)
+        } else {
+            stats.dominant()
+        };
+
+        Document {
+            edits: fix_edits(content, options),
+            text,
+            version,
+            lines,
+            records,
+            header,
+            errors,
+            line_ending,
+            options: options.clone(),
+        }
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.errors
+            .iter()
+            .map(|(error, span)| self.diagnostic(error, span))
+            .collect()
+    }
+
+    fn hover(&self, position: Position) -> Option<Hover> {
+        let offset = self.lines.offset(&self.text, position);
+        let (record_num, span) = self
+            .records
+            .iter()
+            .enumerate()
+            .find(|(_, span)| span.start <= offset && offset <= span.end)?;
+        let fields = tokenizer::fields(&self.text.as_bytes()[span.clone()], &self.options);
+        let i = fields
+            .iter()
+            .position(|field| offset <= span.start + field.end)?;
+        let field = span.start + fields[i].start..span.start + fields[i].end;
+
+        let record = if record_num == 0 {
+            "header".to_string()
+        } else {
+            format!("record {}", record_num)
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::PlainText,
+                value: format!("{}, {}", Column::new(i, self.header.as_ref()), record),
+            }),
+            range: Some(self.lines.range(&self.text, field)),
+        })
+    }
+
+    fn code_actions(&self, uri: &Uri, range: Range) -> Vec<CodeActionOrCommand> {
+        let start = self.lines.offset(&self.text, range.start);
+        let end = self.lines.offset(&self.text, range.end);
+        let overlaps = |span: &std::ops::Range<usize>| span.start <= end && start <= span.end;
+        let mut actions = Vec::new();
+
+        for (error, span) in self.errors.iter().filter(|(_, span)| overlaps(span)) {
+            let diagnostic = self.diagnostic(error, span);
+            match &error.error {
+                CsvErrorKind::InvalidLineEnding | CsvErrorKind::MixedLineEnding { .. } => {
+                    let Some(ending) = self.line_ending else {
+                        continue;
+                    };
+                    let normalized = normalize_line_endings(self.text.as_bytes(), ending);
+                    actions.push(self.action(
+                        uri,
+                        format!("Normalize line endings to {}", ending),
+                        0..self.text.len(),
+                        String::from_utf8_lossy(&normalized).into_owned(),
+                        Some(diagnostic),
+                    ));
+                }
+                CsvErrorKind::LeadingWhitespace(_)
+                | CsvErrorKind::TrailingWhitespace(_)
+                | CsvErrorKind::FormulaInjection(_) => {
+                    let title = if matches!(error.error, CsvErrorKind::FormulaInjection(_)) {
+                        "Prefix the formula with '"
+                    } else {
+                        "Trim whitespace"
+                    };
+                    for edit in self
+                        .edits
+                        .iter()
+                        .filter(|edit| edit.range.start <= span.end && span.start <= edit.range.end)
+                    {
+                        actions.push(self.action(
+                            uri,
+                            title.to_string(),
+                            edit.range.clone(),
+                            edit.text.clone(),
+                            Some(diagnostic.clone()),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (span, text) in self.stray_quotes(start, end) {
+            actions.push(self.action(
+                uri,
+                "Escape quotes by doubling them".to_string(),
+                span,
+                text,
+                None,
+            ));
+        }
+        actions
+    }
+
+    fn diagnostic(&self, error: &CsvError, span: &std::ops::Range<usize>) -> Diagnostic {
+        Diagnostic {
+            range: self.lines.range(&self.text, span.clone()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(error.error.name().to_string())),
+            source: Some("csvlint".to_string()),
+            message: error.error.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn action(
+        &self,
+        uri: &Uri,
+        title: String,
+        span: std::ops::Range<usize>,
+        new_text: String,
+        diagnostic: Option<Diagnostic>,
+    ) -> CodeActionOrCommand {
+        let edit = TextEdit {
+            range: self.lines.range(&self.text, span),
+            new_text,
+        };
+        CodeActionOrCommand::CodeAction(CodeAction {
+            title,
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: diagnostic.map(|diagnostic| vec![diagnostic]),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// Quoted fields between `start` and `end` containing quotes that aren't doubled,
+    /// with their escaped replacement
+    ///
+    /// The csv crate reads `"a "b" c"` as a field that ends after `"a "` and
+    /// continues unquoted, which silently drops quotes.
+    fn stray_quotes(&self, start: usize, end: usize) -> Vec<(std::ops::Range<usize>, String)> {
+        if self.options.lazy_quotes {
+            return Vec::new();
+        }
+        let content = self.text.as_bytes();
+        let mut fixes = Vec::new();
+        for span in self
+            .records
+            .iter()
+            .filter(|span| span.start <= end && start <= span.end)
+        {
+            for field in tokenizer::fields(&content[span.clone()], &self.options) {
+                let field = span.start + field.start..span.start + field.end;
+                let value = &self.text[field.clone()];
+                let Some(inner) = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                else {
+                    continue;
+                };
+                let escaped = inner.replace("\"\"", "\"").replace('"', "\"\"");
+                if escaped != inner {
+                    fixes.push((field, format!("\"{}\"", escaped)));
+                }
+            }
+        }
+        fixes
+    }
+}
+
+/// Byte range of the text an error is about
+fn error_range(
+    error: &CsvError,
+    content: &[u8],
+    records: &[std::ops::Range<usize>],
+    options: &ValidationOptions,
+) -> std::ops::Range<usize> {
+    if error.error.is_line_based() {
+        return csv_line(content, error.record_num);
+    }
+
+    // Errors past the last record, such as a record count limit, point at the end
+    let Some(record) = records.get(error.record_num) else {
+        return content.len()..content.len();
+    };
+    let Some(column) = error.error.column() else {
+        return record.clone();
+    };
+    let quoting = !options.lazy_quotes;
+    let fields = tokenizer::fields(&content[record.clone()], options);
+    let Some(field) = fields.get(column.index - 1) else {
+        return record.clone();
+    };
+    let field = record.start + field.start..record.start + field.end;
+
+    match &error.error {
+        CsvErrorKind::NulByte(character)
+        | CsvErrorKind::ControlCharacter(character)
+        | CsvErrorKind::InvisibleCharacter(character)
+        | CsvErrorKind::BidiControl(character)
+        | CsvErrorKind::LoneSurrogate(character) => {
+            let raw = &content[field.clone()];
+            let quoted = quoting && raw.first() == Some(&b'"');
+            let start = field.start + raw_offset(raw, quoted, character.offset);
+            let len = char::from_u32(character.code_point).map_or(3, char::len_utf8);
+            let end = field.start + raw_offset(raw, quoted, character.offset + len);
+            start..end
+        }
+        _ => field,
+    }
+}
+
+/// Byte offset in the raw `field` of byte `offset` of its value
+///
+/// Each `""` escape of a quoted field is one byte of the value but two of the field.
+fn raw_offset(field: &[u8], quoted: bool, offset: usize) -> usize {
+    if !quoted {
+        return offset.min(field.len());
+    }
+    let mut raw = 1;
+    for _ in 0..offset {
+        if raw >= field.len() {
+            break;
+        }
+        raw += if field[raw] == b'"' && field.get(raw + 1) == Some(&b'"') {
+            2
+        } else {
+            1
+        };
+    }
+    raw.min(field.len())
+}
+
+/// Converts between byte offsets and LSP positions
+///
+/// LSP lines end at CR, LF or CRLF and count characters in UTF-16 code units.
+struct LineIndex {
+    /// Byte offset of the start of each line
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let mut starts = vec![0];
+        for (i, &b) in bytes.iter().enumerate() {
+            if b == b'\n' || (b == b'\r' && bytes.get(i + 1) != Some(&b'\n')) {
+                starts.push(i + 1);
+            }
+        }
+        LineIndex { starts }
+    }
+
+    fn position(&self, text: &str, offset: usize) -> Position {
+        // Ranges found from raw bytes may not fall on a character boundary
+        let mut offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.starts[line];
+        Position {
+            line: line as u32,
+            character: text[start..offset].encode_utf16().count() as u32,
+        }
+    }
+
+    fn range(&self, text: &str, span: std::ops::Range<usize>) -> Range {
+        Range {
+            start: self.position(text, span.start),
+            end: self.position(text, span.end),
+        }
+    }
+
+    /// Byte offset of `position`, clamped to the end of its line
+    fn offset(&self, text: &str, position: Position) -> usize {
+        let Some(&start) = self.starts.get(position.line as usize) else {
+            return text.len();
+        };
+        let mut units = 0;
+        for (i, c) in text[start..].char_indices() {
+            if units >= position.character as usize || c == '\r' || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        text.len()
+    }
+}
+
+/// Byte range of a line as numbered by csvlint, without its terminator
+fn csv_line(content: &[u8], line_num: usize) -> std::ops::Range<usize> {
+    let mut range = content.len()..content.len();
+    let mut current = 0;
+    for_each_line(content, |line, _| {
+        current += 1;
+        if current == line_num {
+            range = line;
+        }
+    });
+    range
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FieldScope;
+    use lsp_types::{
+        DidOpenTextDocumentParams, HoverParams, TextDocumentIdentifier, TextDocumentItem,
+        TextDocumentPositionParams,
+    };
+    use std::str::FromStr;
+
+    fn options() -> ValidationOptions {
+        ValidationOptions {
+            surrounding_whitespace: FieldScope::All,
+            characters: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diagnostic_ranges() {
+        let text = "id,name\r\n1, ü\u{200b}x\r\n2,b\n".to_string();
+        let document = Document::new(text, 1, &options());
+        let diagnostics: Vec<(String, Range)> = document
+            .diagnostics()
+            .into_iter()
+            .map(|d| (d.message, d.range))
+            .collect();
+        let range = |line, start, end| Range {
+            start: Position {
+                line,
+                character: start,
+            },
+            end: Position {
+                line,
+                character: end,
+            },
+        };
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    "mixed line endings: found LF, file predominantly uses CRLF".to_string(),
+                    range(2, 0, 3)
+                ),
+                (
+                    "leading whitespace in column 2 (\"name\")".to_string(),
+                    range(1, 2, 6)
+                ),
+                (
+                    "invisible character: U+200B in column 2 (\"name\") at byte 3".to_string(),
+                    range(1, 4, 5)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_character_after_escaped_quote() {
+        let text = "id,name\n1,\"\"\"\u{200b}\"\n".to_string();
+        let document = Document::new(text, 1, &options());
+        let ranges: Vec<Range> = document
+            .diagnostics()
+            .into_iter()
+            .map(|d| d.range)
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![Range {
+                start: Position::new(1, 5),
+                end: Position::new(1, 6),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_code_actions() {
+        let uri = Uri::from_str("file:///data.csv").unwrap();
+        let text = "id,name\r\n1, a\r\n2,\"say \"hi\"\"\n".to_string();
+        let document = Document::new(text, 1, &options());
+        let everything = Range {
+            start: Position::new(0, 0),
+            end: Position::new(3, 0),
+        };
+        let actions: Vec<(String, String)> = document
+            .code_actions(&uri, everything)
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => {
+                    let (changed, edits) = action
+                        .edit
+                        .unwrap()
+                        .changes
+                        .unwrap()
+                        .drain()
+                        .next()
+                        .unwrap();
+                    assert_eq!(changed, uri);
+                    (action.title, edits[0].new_text.clone())
+                }
+                CodeActionOrCommand::Command(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (
+                    "Normalize line endings to CRLF".to_string(),
+                    "id,name\r\n1, a\r\n2,\"say \"hi\"\"\r\n".to_string()
+                ),
+                ("Trim whitespace".to_string(), "a".to_string()),
+                (
+                    "Escape quotes by doubling them".to_string(),
+                    "\"say \"\"hi\"\"\"".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scripted_session() {
+        let (server, client) = Connection::memory();
+        let thread = std::thread::spawn(move || serve_connection(&server, &options()).unwrap());
+        let uri = Uri::from_str("file:///data.csv").unwrap();
+        let request = |id: i32, method: &str, params: serde_json::Value| {
+            Message::Request(Request::new(id.into(), method.to_string(), params))
+        };
+        let notification = |method: &str, params: serde_json::Value| {
+            Message::Notification(Notification::new(method.to_string(), params))
+        };
+
+        client
+            .sender
+            .send(request(
+                1,
+                "initialize",
+                serde_json::json!({"capabilities": {}}),
+            ))
+            .unwrap();
+        assert!(matches!(
+            client.receiver.recv().unwrap(),
+            Message::Response(_)
+        ));
+        client
+            .sender
+            .send(notification("initialized", serde_json::json!({})))
+            .unwrap();
+
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "csv".to_string(),
+                version: 1,
+                text: "id,name\n1, a\n".to_string(),
+            },
+        };
+        client
+            .sender
+            .send(notification(
+                DidOpenTextDocument::METHOD,
+                serde_json::to_value(params).unwrap(),
+            ))
+            .unwrap();
+        let Message::Notification(published) = client.receiver.recv().unwrap() else {
+            panic!("expected diagnostics");
+        };
+        let published: PublishDiagnosticsParams = serde_json::from_value(published.params).unwrap();
+        assert_eq!(published.diagnostics.len(), 1);
+
+        let params = HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(1, 3),
+            },
+            work_done_progress_params: Default::default(),
+        };
+        client
+            .sender
+            .send(request(
+                2,
+                HoverRequest::METHOD,
+                serde_json::to_value(params).unwrap(),
+            ))
+            .unwrap();
+        let Message::Response(response) = client.receiver.recv().unwrap() else {
+            panic!("expected a hover response");
+        };
+        let hover: Hover = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert!(matches!(
+            hover.contents,
+            HoverContents::Markup(content) if content.value == "column 2 (\"name\"), record 1"
+        ));
+
+        client
+            .sender
+            .send(request(3, "shutdown", serde_json::Value::Null))
+            .unwrap();
+        assert!(matches!(
+            client.receiver.recv().unwrap(),
+            Message::Response(_)
+        ));
+        client
+            .sender
+            .send(notification("exit", serde_json::Value::Null))
+            .unwrap();
+        thread.join().unwrap();
+    }
+}
@@ -1,17 +1,142 @@
 use clap::Parser;
-use csvlint::validate;
-use std::fs::File;
-use std::io::{self, BufReader};
+use csvlint::baseline::{Baseline, BaselineOutcome};
+use csvlint::changes::RecordChanges;
+use csvlint::decompress::decompress;
+use csvlint::fix::fix_slice;
+use csvlint::fixed_width::FixedWidthSpec;
+use csvlint::git::{file_at_revision, staged_file};
+#[cfg(feature = "parallel")]
+use csvlint::parallel::validate_parallel_with_options;
+use csvlint::report::group_errors;
+use csvlint::schema::Schema;
+use csvlint::skip::{self, SkipLines};
+use csvlint::stats::{StatsOptions, profile};
+use csvlint::suppress::{SuppressionError, Suppressions};
+use csvlint::tokenizer::Delimiter;
+use csvlint::{
+    CsvError, CsvErrorKind, FieldLengthLimit, FieldScope, FinalNewline, LengthUnit, Sample,
+    SizeLimits, UniqueMode, ValidationOptions, ValidationResult, validate_slice_with_options,
+    validate_with_options,
+};
+#[cfg(feature = "mmap")]
+use csvlint::{decompress::Compression, mmap};
+#[cfg(feature = "watch")]
+use csvlint::{
+    report::diff_errors,
+    watch::{FileWatcher, csv_files, is_csv_file},
+};
+use regex::bytes::Regex;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
+use std::path::Path;
 use std::process;
+#[cfg(feature = "watch")]
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// False positive rate of the Bloom filter used by `--bloom-filter`
+const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.001;
 
 /// A CSV linter that validates CSV files according to RFC 4180
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Field delimiter in the file (e.g., ',' '\t' '|' ':' ';')
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    dialect: DialectArgs,
+
+    #[command(flatten)]
+    rules: RuleArgs,
+
+    /// Write a copy of the file with whitespace and formula errors fixed to OUTPUT
+    #[arg(long, value_name = "OUTPUT")]
+    fix: Option<String>,
+
+    /// Number of threads to validate with (0 uses all cores)
+    #[cfg(feature = "parallel")]
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Stop after this many errors
+    #[arg(long, value_name = "N")]
+    max_errors: Option<usize>,
+
+    /// Stop at the first error (same as --max-errors 1)
+    #[arg(long, default_value_t = false)]
+    fail_fast: bool,
+
+    /// Report at most N errors of a kind, e.g. InvalidLineEnding=100 (repeatable)
+    #[arg(long, value_name = "KIND=N", value_parser = parse_kind_limit)]
+    max_per_kind: Vec<(String, usize)>,
+
+    /// Only check a sample: the first N rows ("1000") or a random percentage ("5%")
+    #[arg(long, value_name = "SPEC", value_parser = parse_sample)]
+    sample: Option<Sample>,
+
+    /// Print every error on its own line instead of grouping identical errors
+    #[arg(long, default_value_t = false)]
+    no_group: bool,
+
+    /// Number of example errors printed for each group
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    examples: usize,
+
+    /// Seed for random sampling
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Schema file declaring foreign keys between files
+    #[arg(long, value_name = "FILE")]
+    schema: Option<String>,
+
+    /// Validate fixed-width files instead of CSV, with columns declared in this spec file
+    #[arg(long, value_name = "SPEC", conflicts_with = "fix")]
+    fixed_width: Option<String>,
+
+    /// Don't report errors recorded in this baseline file, and list its entries that no longer occur
+    #[arg(long, value_name = "FILE")]
+    baseline: Option<String>,
+
+    /// Record the errors found in a baseline file for later runs with --baseline
+    #[arg(long, value_name = "FILE", conflicts_with = "baseline")]
+    write_baseline: Option<String>,
+
+    /// Validate the content of the files staged in the git index rather than the files
+    #[arg(long, default_value_t = false, conflicts_with = "fix")]
+    staged: bool,
+
+    /// Report only errors on records added or changed since this git revision, and file-level errors
+    #[arg(long, value_name = "REF")]
+    diff_base: Option<String>,
+
+    /// Print the lines skipped by --skip-rows, --header-row, --skip-footer and --footer
+    #[arg(short, long, default_value_t = false)]
+    verbose: bool,
+
+    /// Keep running, re-validating files when they change and printing how their errors changed
+    #[cfg(feature = "watch")]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["fix", "write_baseline", "diff_base", "staged"])]
+    watch: bool,
+
+    /// CSV files to validate, or with --watch, directories to watch for CSV files
+    #[arg(required = true)]
+    files: Vec<String>,
+}
+
+/// Options describing how the file is parsed, shared by all commands
+#[derive(clap::Args)]
+struct DialectArgs {
+    /// Field delimiter in the file (e.g., ',' '\t' '|' ':' ';' '||' '~|~' '¦')
     #[arg(short, long, default_value = ",")]
     delimiter: String,
 
+    /// Split fields on matches of this regular expression instead of --delimiter
+    #[arg(long, value_name = "REGEX", value_parser = parse_delimiter_regex, conflicts_with = "delimiter")]
+    delimiter_regex: Option<Delimiter>,
+
     /// Try to parse improperly escaped quotes
     #[arg(short, long, default_value_t = false)]
     lazyquotes: bool,
@@ -20,16 +145,306 @@ struct Args {
     #[arg(long, default_value_t = false)]
     rfc4180: bool,
 
-    /// CSV file to validate
+    /// Treat lines starting with this character as comments (reported in --rfc4180 mode)
+    #[arg(long, value_name = "CHAR", value_parser = parse_comment)]
+    comment: Option<u8>,
+
+    /// Skip this many lines, such as titles, before the header
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    skip_rows: usize,
+
+    /// Line number of the header, skipping the lines before it (same as --skip-rows N-1)
+    #[arg(long, value_name = "N", conflicts_with = "skip_rows", value_parser = parse_header_row)]
+    header_row: Option<usize>,
+
+    /// Skip this many lines, such as totals, at the end of the file
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    skip_footer: usize,
+
+    /// Skip the first line after the header that matches this regex, and every line after it
+    #[arg(long, value_name = "REGEX", value_parser = parse_footer)]
+    footer: Option<Regex>,
+}
+
+/// Options selecting the rules files are checked against
+#[derive(clap::Args)]
+struct RuleArgs {
+    /// Don't report lines whose terminator differs from the file's dominant one
+    #[arg(long, default_value_t = false)]
+    allow_mixed_line_endings: bool,
+
+    /// Whether the file must end with a line break: "require" or "forbid"
+    #[arg(long, value_name = "RULE", value_parser = parse_final_newline)]
+    final_newline: Option<FinalNewline>,
+
+    /// Report empty lines between records, which are otherwise skipped
+    #[arg(long, default_value_t = false)]
+    forbid_blank_lines: bool,
+
+    /// Report empty lines at the end of the file
+    #[arg(long, default_value_t = false)]
+    forbid_trailing_blank_lines: bool,
+
+    /// Report leading or trailing whitespace in "unquoted", "quoted" or "all" fields
+    #[arg(long, value_name = "FIELDS", value_parser = parse_field_scope)]
+    whitespace: Option<FieldScope>,
+
+    /// Report non-breaking spaces inside fields
+    #[arg(long, default_value_t = false)]
+    forbid_nbsp: bool,
+
+    /// Report tabs inside fields, unless tab is the delimiter
+    #[arg(long, default_value_t = false)]
+    forbid_tabs: bool,
+
+    /// Report fields a spreadsheet could evaluate as formulas (CSV injection)
+    #[arg(long, default_value_t = false)]
+    forbid_formulas: bool,
+
+    /// Report NUL bytes, control characters, invisible characters, bidi controls and lone surrogates
+    #[arg(long, default_value_t = false)]
+    forbid_control_chars: bool,
+
+    /// Never report these code points, e.g. U+200D or U+0000-U+001F (repeatable)
+    #[arg(long, value_name = "RANGE", value_parser = parse_char_range)]
+    allow_chars: Vec<RangeInclusive<u32>>,
+
+    /// Report records identical to an earlier record
+    #[arg(long, default_value_t = false)]
+    unique_rows: bool,
+
+    /// Report records whose values in these columns repeat an earlier record's, e.g. id or col1,col2
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    unique_key: Vec<String>,
+
+    /// Check uniqueness with a Bloom filter sized for this many rows instead of an exact hash set
+    #[arg(long, value_name = "EXPECTED_ROWS")]
+    bloom_filter: Option<usize>,
+
+    /// Report fields longer than N bytes, in every column or one: 255 or name=255 (repeatable)
+    #[arg(long, value_name = "[COLUMN=]N", value_parser = parse_field_length)]
+    max_field_bytes: Vec<(Option<String>, usize)>,
+
+    /// Report fields longer than N characters, in every column or one: 255 or name=255 (repeatable)
+    #[arg(long, value_name = "[COLUMN=]N", value_parser = parse_field_length)]
+    max_field_chars: Vec<(Option<String>, usize)>,
+
+    /// Report records with more than N fields
+    #[arg(long, value_name = "N")]
+    max_fields: Option<usize>,
+
+    /// Report records longer than N bytes, skipping them without parsing
+    ///
+    /// Only memory-mapped files are scanned in place; stdin, pipes and compressed files are
+    /// read into memory first, so the limit doesn't bound their memory use.
+    #[arg(long, value_name = "N")]
+    max_record_bytes: Option<usize>,
+
+    /// Report files with more than N data records
+    #[arg(long, value_name = "N")]
+    max_records: Option<usize>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Print per-column statistics of a file
+    Stats(StatsArgs),
+    /// Run a language server over stdio, publishing diagnostics to editors
+    #[cfg(feature = "lsp")]
+    Lsp(LspArgs),
+}
+
+#[derive(clap::Args)]
+struct StatsArgs {
+    #[command(flatten)]
+    dialect: DialectArgs,
+
+    /// Number of most frequent values shown per column
+    #[arg(long, value_name = "K", default_value_t = 5)]
+    top: usize,
+
+    /// Estimate distinct counts and top values in constant memory (HyperLogLog)
+    #[arg(long, default_value_t = false)]
+    approximate: bool,
+
+    /// Print the statistics as JSON
+    #[arg(long, default_value_t = false)]
+    json: bool,
+
+    /// CSV file to profile
     file: String,
 }
 
+#[cfg(feature = "lsp")]
+#[derive(clap::Args)]
+struct LspArgs {
+    #[command(flatten)]
+    dialect: DialectArgs,
+
+    #[command(flatten)]
+    rules: RuleArgs,
+}
+
 fn main() {
     let args = Args::parse();
+    match &args.command {
+        Some(Command::Stats(stats_args)) => process::exit(stats(stats_args)),
+        #[cfg(feature = "lsp")]
+        Some(Command::Lsp(lsp_args)) => process::exit(lsp(lsp_args)),
+        None => {}
+    }
+
+    let mut options = dialect_options(&args.dialect);
+    rule_options(&mut options, &args.rules);
+    options.limits.max_errors = if args.fail_fast {
+        Some(1)
+    } else {
+        args.max_errors
+    };
+    options.limits.max_per_kind = args.max_per_kind.iter().cloned().collect();
+    options.sample = match args.sample {
+        Some(Sample::Percent { percent, .. }) => Sample::Percent {
+            percent,
+            seed: args.seed,
+        },
+        Some(sample) => sample,
+        None => Sample::All,
+    };
+
+    // Warn if not using defaults (unless in RFC 4180 mode)
+    let dialect = &args.dialect;
+    if !dialect.rfc4180
+        && (dialect.delimiter != "," || dialect.delimiter_regex.is_some() || dialect.lazyquotes)
+    {
+        eprintln!("Warning: not using defaults, may not validate CSV to RFC 4180");
+    }
+
+    if dialect.rfc4180 {
+        println!("Running in strict RFC 4180 compliance mode");
+        println!("- Delimiter: comma (,)");
+        println!("- Line endings: CRLF required");
+        println!("- Quote escaping: strict");
+        println!();
+    }
+
+    #[cfg(feature = "parallel")]
+    let jobs = args.jobs;
+    #[cfg(not(feature = "parallel"))]
+    let jobs = 1;
+
+    if args.fix.is_some() && args.files.len() > 1 {
+        eprintln!("--fix can only be used with a single file");
+        process::exit(1);
+    }
 
+    if let Some(path) = &args.fixed_width {
+        match FixedWidthSpec::load(Path::new(path)) {
+            Ok(spec) => options.fixed_width = Some(spec),
+            Err(e) => {
+                eprintln!("error loading fixed-width spec: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let schema = args.schema.as_ref().map(|path| {
+        Schema::load(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("error loading schema '{}': {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    let mut baseline = if let Some(path) = &args.baseline {
+        match Baseline::load(Path::new(path)) {
+            Ok(baseline) => BaselineAction::Apply(baseline),
+            Err(e) => {
+                eprintln!("error loading baseline: {}", e);
+                process::exit(1);
+            }
+        }
+    } else if args.write_baseline.is_some() {
+        BaselineAction::Write(Baseline::default())
+    } else {
+        BaselineAction::None
+    };
+
+    #[cfg(feature = "watch")]
+    if args.watch {
+        let baseline = match &baseline {
+            BaselineAction::Apply(baseline) => Some(baseline),
+            _ => None,
+        };
+        process::exit(watch(&args, &options, schema.as_ref(), baseline, jobs));
+    }
+
+    let mut exit_code = 0;
+    for (i, file) in args.files.iter().enumerate() {
+        if args.files.len() > 1 {
+            if i > 0 {
+                println!();
+            }
+            println!("==> {} <==", file);
+        }
+
+        let Some(options) = file_options(Path::new(file), &options, schema.as_ref()) else {
+            exit_code = worst_exit_code(exit_code, 1);
+            continue;
+        };
+        let file_exit_code = lint_file(file, &args, &options, &mut baseline, jobs);
+        exit_code = worst_exit_code(exit_code, file_exit_code);
+    }
+
+    if let (BaselineAction::Write(baseline), Some(path)) = (&baseline, &args.write_baseline) {
+        if let Err(e) = baseline.save(Path::new(path)) {
+            eprintln!("error writing baseline: {}", e);
+            process::exit(1);
+        }
+        eprintln!(
+            "baseline with {} error(s) written to '{}'",
+            baseline.entries.len(),
+            path
+        );
+        // The errors are accepted, so only failures count
+        if exit_code == 2 {
+            exit_code = 0;
+        }
+    }
+    process::exit(exit_code);
+}
+
+/// What to do with the errors found, given `--baseline` or `--write-baseline`
+enum BaselineAction {
+    None,
+    /// Don't report the errors in this baseline
+    Apply(Baseline),
+    /// Add the errors to this baseline
+    Write(Baseline),
+}
+
+/// The options for one file, with the foreign keys the schema declares for it
+fn file_options(
+    path: &Path,
+    options: &ValidationOptions,
+    schema: Option<&Schema>,
+) -> Option<ValidationOptions> {
+    let mut options = options.clone();
+    if let Some(schema) = schema {
+        match schema.foreign_key_checks(path, &options) {
+            Ok(checks) => options.foreign_keys = checks,
+            Err(e) => {
+                eprintln!("error loading foreign keys of '{}': {}", path.display(), e);
+                return None;
+            }
+        }
+    }
+    Some(options)
+}
+
+/// Builds the options for the parsing dialect, exiting on an invalid delimiter
+fn dialect_options(args: &DialectArgs) -> ValidationOptions {
     // Handle RFC 4180 strict mode
-    let (delimiter_byte, lazy_quotes) = if args.rfc4180 {
-        if args.delimiter != "," {
+    let (delimiter, lazy_quotes) = if args.rfc4180 {
+        if args.delimiter != "," || args.delimiter_regex.is_some() {
             eprintln!(
                 "Warning: --rfc4180 mode requires comma delimiter, ignoring --delimiter option"
             );
@@ -37,68 +452,329 @@ fn main() {
         if args.lazyquotes {
             eprintln!("Warning: --rfc4180 mode disables lazy quotes, ignoring --lazyquotes option");
         }
-        (b',', false)
+        (Delimiter::Literal(vec![b',']), false)
+    } else if let Some(delimiter) = &args.delimiter_regex {
+        (delimiter.clone(), args.lazyquotes)
     } else {
         // Validate and convert delimiter
-        let delimiter_byte = match parse_delimiter(&args.delimiter) {
-            Ok(d) => d,
+        match parse_delimiter(&args.delimiter) {
+            Ok(d) => (Delimiter::Literal(d), args.lazyquotes),
             Err(e) => {
                 eprintln!("{}", e);
                 process::exit(1);
             }
+        }
+    };
+
+    if let (Some(comment), Delimiter::Literal(bytes)) = (args.comment, &delimiter)
+        && bytes.first() == Some(&comment)
+    {
+        eprintln!("the comment character can't be the delimiter");
+        process::exit(1);
+    }
+
+    // The csv crate splits on single bytes; anything longer goes to the tokenizer
+    let (delimiter_byte, custom_delimiter) = match delimiter {
+        Delimiter::Literal(bytes) if bytes.len() == 1 => (bytes[0], None),
+        delimiter => (b',', Some(delimiter)),
+    };
+
+    ValidationOptions {
+        custom_delimiter,
+        comment: args.comment,
+        skip: SkipLines {
+            preamble: args.header_row.map_or(args.skip_rows, |row| row - 1),
+            footer: args.skip_footer,
+            footer_pattern: args.footer.clone(),
+        },
+        ..ValidationOptions::new(delimiter_byte, lazy_quotes, args.rfc4180)
+    }
+}
+
+/// Applies the rules selected on the command line to `options`
+fn rule_options(options: &mut ValidationOptions, rules: &RuleArgs) {
+    options.mixed_line_endings = !rules.allow_mixed_line_endings;
+    options.final_newline = rules.final_newline.unwrap_or_default();
+    options.blank_lines = rules.forbid_blank_lines;
+    options.trailing_blank_lines = rules.forbid_trailing_blank_lines;
+    options.surrounding_whitespace = rules.whitespace.unwrap_or_default();
+    options.non_breaking_spaces = rules.forbid_nbsp;
+    options.tabs = rules.forbid_tabs;
+    options.formula_injection = rules.forbid_formulas;
+    options.characters = rules.forbid_control_chars;
+    options
+        .allowed_characters
+        .extend(rules.allow_chars.iter().cloned());
+    options.unique_rows = rules.unique_rows;
+    options.unique_key = rules.unique_key.clone();
+    if let Some(expected_rows) = rules.bloom_filter {
+        options.unique_mode = UniqueMode::Bloom {
+            expected_rows,
+            false_positive_rate: BLOOM_FALSE_POSITIVE_RATE,
         };
-        (delimiter_byte, args.lazyquotes)
+    }
+    let field_lengths = |limits: &[(Option<String>, usize)], unit| {
+        limits
+            .iter()
+            .map(move |(column, max)| FieldLengthLimit {
+                column: column.clone(),
+                max: *max,
+                unit,
+            })
+            .collect::<Vec<_>>()
+    };
+    options.size_limits = SizeLimits {
+        field_lengths: [
+            field_lengths(&rules.max_field_bytes, LengthUnit::Bytes),
+            field_lengths(&rules.max_field_chars, LengthUnit::Chars),
+        ]
+        .concat(),
+        max_fields: rules.max_fields,
+        max_record_bytes: rules.max_record_bytes,
+        max_records: rules.max_records,
     };
+}
 
-    // Warn if not using defaults (unless in RFC 4180 mode)
-    if !args.rfc4180 && (args.delimiter != "," || args.lazyquotes) {
-        eprintln!("Warning: not using defaults, may not validate CSV to RFC 4180");
+/// Combines exit codes: failures (1) outrank validation errors (2), which outrank success
+fn worst_exit_code(a: i32, b: i32) -> i32 {
+    let rank = |code| match code {
+        0 => 0,
+        2 => 1,
+        _ => 2,
+    };
+    if rank(b) > rank(a) { b } else { a }
+}
+
+/// Validates one file and prints its report, returning the exit code for it
+fn lint_file(
+    path: &str,
+    args: &Args,
+    options: &ValidationOptions,
+    baseline: &mut BaselineAction,
+    jobs: usize,
+) -> i32 {
+    let Some(mut result) = check_file(Path::new(path), args.staged, options, jobs) else {
+        return 1;
+    };
+
+    if args.verbose && options.skip.is_enabled() {
+        let printed = with_content(Path::new(path), args.staged, |content| {
+            print_skipped_lines(content, options)
+        });
+        if let Err(e) = printed {
+            eprintln!("error reading file '{}': {}", path, e);
+            return 1;
+        }
     }
 
-    if args.rfc4180 {
-        println!("Running in strict RFC 4180 compliance mode");
-        println!("- Delimiter: comma (,)");
-        println!("- Line endings: CRLF required");
-        println!("- Quote escaping: strict");
-        println!();
+    if let Some(output) = &args.fix {
+        match fix_file(Path::new(path), Path::new(output), options) {
+            Ok(fixes) => eprintln!("fixed {} field(s), written to '{}'", fixes, output),
+            Err(e) => {
+                eprintln!("error fixing file '{}': {}", path, e);
+                return 1;
+            }
+        }
     }
 
-    // Open and validate the file
-    let file = match File::open(&args.file) {
-        Ok(f) => f,
+    if let Some(revision) = &args.diff_base {
+        let errors = std::mem::take(&mut result.errors);
+        match changed_since(Path::new(path), args.staged, revision, errors, options) {
+            Ok((errors, changed)) => {
+                println!("{} record(s) added or changed since {}", changed, revision);
+                result.errors = errors;
+            }
+            Err(e) => {
+                eprintln!("error comparing '{}' with {}: {}", path, revision, e);
+                return 1;
+            }
+        }
+    }
+
+    let mut suppressions = match Suppressions::for_file(Path::new(path)) {
+        Ok(suppressions) => suppressions,
         Err(e) => {
-            if e.kind() == io::ErrorKind::NotFound {
-                eprintln!("file '{}' does not exist", args.file);
-            } else {
-                eprintln!("error opening file '{}': {}", args.file, e);
+            eprintln!("error loading ignore file: {}", e);
+            return 1;
+        }
+    };
+    let mut suppressed = 0;
+    if !suppressions.is_empty() || options.comment.is_some() {
+        let applied = with_content(Path::new(path), args.staged, |content| {
+            suppressions.add_directives(Path::new(path), content, options)?;
+            suppressed = suppressions.apply(&mut result.errors, content, options);
+            Ok::<(), SuppressionError>(())
+        });
+        match applied {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                eprintln!("error in suppression directive: {}", e);
+                return 1;
+            }
+            Err(e) => {
+                eprintln!("error reading file '{}': {}", path, e);
+                return 1;
             }
-            process::exit(1);
         }
+    }
+
+    let mut outcome = None;
+    let read = match baseline {
+        BaselineAction::None => Ok(()),
+        BaselineAction::Apply(baseline) => {
+            let errors = std::mem::take(&mut result.errors);
+            with_content(Path::new(path), args.staged, |content| {
+                let applied = baseline.apply(path, errors, content, options);
+                result.errors.clone_from(&applied.errors);
+                outcome = Some(applied);
+            })
+        }
+        BaselineAction::Write(baseline) => with_content(Path::new(path), args.staged, |content| {
+            baseline.add(path, &result.errors, content, options)
+        }),
     };
+    if let Err(e) = read {
+        eprintln!("error reading file '{}': {}", path, e);
+        return 1;
+    }
 
-    let reader = BufReader::new(file);
+    let exit_code = report(&result, args, options);
+    if suppressed > 0 {
+        println!(
+            "\n{} error(s) suppressed by ignore files or directives",
+            suppressed
+        );
+    }
+    if let Some(outcome) = outcome {
+        print_baseline_outcome(&outcome);
+    }
+    exit_code
+}
 
-    let result = match validate(reader, delimiter_byte, lazy_quotes, args.rfc4180) {
-        Ok(result) => result,
+/// Keeps the errors about the file or records added or changed since the git
+/// `revision`, returning them and the number of such records
+fn changed_since(
+    path: &Path,
+    staged: bool,
+    revision: &str,
+    errors: Vec<CsvError>,
+    options: &ValidationOptions,
+) -> Result<(Vec<CsvError>, usize), Box<dyn std::error::Error>> {
+    let old = match file_at_revision(path, revision)? {
+        Some(blob) => {
+            let (_, mut reader) = decompress(io::Cursor::new(blob))?;
+            let mut old = Vec::new();
+            reader.read_to_end(&mut old)?;
+            Some(old)
+        }
+        None => None,
+    };
+    let filtered = with_content(path, staged, |content| {
+        let changes = RecordChanges::new(old.as_deref(), content, options);
+        (changes.filter(errors), changes.changed_records())
+    })?;
+    Ok(filtered)
+}
+
+/// Prints how many errors the baseline suppressed, and which of its entries no longer occur
+fn print_baseline_outcome(outcome: &BaselineOutcome) {
+    if outcome.suppressed > 0 {
+        println!(
+            "\n{} known error(s) suppressed by the baseline",
+            outcome.suppressed
+        );
+    }
+    if !outcome.fixed.is_empty() {
+        println!(
+            "\n{} baseline error(s) no longer occur, update the baseline with --write-baseline:",
+            outcome.fixed.len()
+        );
+        for entry in &outcome.fixed {
+            println!("  {}", entry);
+        }
+    }
+}
+
+/// Validates one file, printing why if it can't be read
+///
+/// With `staged`, the content staged in the git index is validated instead of the file.
+fn check_file(
+    path: &Path,
+    staged: bool,
+    options: &ValidationOptions,
+    jobs: usize,
+) -> Option<ValidationResult> {
+    let input = if staged {
+        open_staged(path)
+    } else {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => {
+                if e.kind() == io::ErrorKind::NotFound {
+                    eprintln!("file '{}' does not exist", path.display());
+                } else {
+                    eprintln!("error opening file '{}': {}", path.display(), e);
+                }
+                return None;
+            }
+        };
+        open_input(file)
+    };
+
+    let result = match input {
+        Ok(input) => validate_input(input, jobs, options),
         Err(e) => {
-            eprintln!("validation error: {}", e);
-            process::exit(1);
+            eprintln!("error reading file '{}': {}", path.display(), e);
+            return None;
         }
     };
 
-    // Handle results
+    match result {
+        Ok(result) => Some(result),
+        Err(e) => {
+            eprintln!("validation error: {}", e);
+            None
+        }
+    }
+}
+
+/// Prints the lines of `content` skipped before the header and at the end
+fn print_skipped_lines(content: &[u8], options: &ValidationOptions) {
+    for (line, range) in skip::data(content, options).skipped {
+        println!(
+            "skipped line {}: {}",
+            line,
+            String::from_utf8_lossy(&content[range])
+        );
+    }
+}
+
+/// Prints the report of a validated file, returning the exit code for it
+fn report(result: &ValidationResult, args: &Args, options: &ValidationOptions) -> i32 {
     if result.errors.is_empty() {
-        if args.rfc4180 {
+        if options.sample != Sample::All {
+            println!("sampled rows are valid");
+        } else if args.dialect.rfc4180 {
             println!("file is valid and complies with RFC 4180");
         } else {
             println!("file is valid");
         }
-        process::exit(0);
+        return 0;
     }
 
     // Count different types of errors
     let mut field_count_errors = 0;
     let mut line_ending_errors = 0;
+    let mut mixed_line_ending_errors = 0;
+    let mut blank_line_errors = 0;
+    let mut comment_errors = 0;
+    let mut fixed_width_errors = 0;
+    let mut whitespace_errors = 0;
+    let mut formula_errors = 0;
+    let mut character_errors = 0;
+    let mut duplicate_errors = 0;
+    let mut reference_errors = 0;
+    let mut size_errors = 0;
     let mut quote_errors = 0;
     let mut other_errors = 0;
 
@@ -106,6 +782,33 @@ fn main() {
         match error.error {
             csvlint::CsvErrorKind::FieldCount => field_count_errors += 1,
             csvlint::CsvErrorKind::InvalidLineEnding => line_ending_errors += 1,
+            csvlint::CsvErrorKind::MixedLineEnding { .. } => mixed_line_ending_errors += 1,
+            csvlint::CsvErrorKind::BlankLine
+            | csvlint::CsvErrorKind::TrailingBlankLine
+            | csvlint::CsvErrorKind::MissingFinalNewline
+            | csvlint::CsvErrorKind::UnexpectedFinalNewline => blank_line_errors += 1,
+            csvlint::CsvErrorKind::CommentLine => comment_errors += 1,
+            csvlint::CsvErrorKind::LineLength { .. }
+            | csvlint::CsvErrorKind::Padding(_)
+            | csvlint::CsvErrorKind::Alignment(_)
+            | csvlint::CsvErrorKind::FieldType { .. } => fixed_width_errors += 1,
+            csvlint::CsvErrorKind::LeadingWhitespace(_)
+            | csvlint::CsvErrorKind::TrailingWhitespace(_)
+            | csvlint::CsvErrorKind::NonBreakingSpace(_)
+            | csvlint::CsvErrorKind::Tab(_) => whitespace_errors += 1,
+            csvlint::CsvErrorKind::FormulaInjection(_) => formula_errors += 1,
+            csvlint::CsvErrorKind::NulByte(_)
+            | csvlint::CsvErrorKind::ControlCharacter(_)
+            | csvlint::CsvErrorKind::InvisibleCharacter(_)
+            | csvlint::CsvErrorKind::BidiControl(_)
+            | csvlint::CsvErrorKind::LoneSurrogate(_) => character_errors += 1,
+            csvlint::CsvErrorKind::DuplicateRow { .. }
+            | csvlint::CsvErrorKind::DuplicateKey { .. } => duplicate_errors += 1,
+            csvlint::CsvErrorKind::MissingReference { .. } => reference_errors += 1,
+            csvlint::CsvErrorKind::FieldTooLong { .. }
+            | csvlint::CsvErrorKind::TooManyFields { .. }
+            | csvlint::CsvErrorKind::RecordTooLong { .. }
+            | csvlint::CsvErrorKind::TooManyRecords { .. } => size_errors += 1,
             csvlint::CsvErrorKind::BareQuote
             | csvlint::CsvErrorKind::Quote
             | csvlint::CsvErrorKind::UnterminatedQuote => quote_errors += 1,
@@ -124,6 +827,52 @@ fn main() {
             line_ending_errors
         );
     }
+    if mixed_line_ending_errors > 0 {
+        let stats = result.line_endings;
+        println!(
+            "  - {} mixed line ending error(s) ({} CRLF, {} LF, {} CR lines)",
+            mixed_line_ending_errors, stats.crlf, stats.lf, stats.cr
+        );
+    }
+    if blank_line_errors > 0 {
+        println!(
+            "  - {} blank line or final newline error(s)",
+            blank_line_errors
+        );
+    }
+    if comment_errors > 0 {
+        println!(
+            "  - {} comment line error(s) (RFC 4180 has no comments)",
+            comment_errors
+        );
+    }
+    if fixed_width_errors > 0 {
+        println!(
+            "  - {} line length, padding, alignment or type error(s)",
+            fixed_width_errors
+        );
+    }
+    if whitespace_errors > 0 {
+        println!("  - {} whitespace error(s)", whitespace_errors);
+    }
+    if formula_errors > 0 {
+        println!("  - {} formula injection error(s)", formula_errors);
+    }
+    if character_errors > 0 {
+        println!(
+            "  - {} control or invisible character error(s)",
+            character_errors
+        );
+    }
+    if duplicate_errors > 0 {
+        println!("  - {} duplicate row or key error(s)", duplicate_errors);
+    }
+    if reference_errors > 0 {
+        println!("  - {} missing reference error(s)", reference_errors);
+    }
+    if size_errors > 0 {
+        println!("  - {} size limit error(s)", size_errors);
+    }
     if quote_errors > 0 {
         println!("  - {} quote/escaping error(s)", quote_errors);
     }
@@ -132,48 +881,637 @@ fn main() {
     }
     println!();
 
-    // Print all errors
-    for error in &result.errors {
-        println!("{}", error);
+    // Print all errors, or one summary line per kind with a few examples
+    if args.no_group {
+        for error in &result.errors {
+            println!("{}", error);
+        }
+    } else {
+        for group in group_errors(&result.errors, args.examples) {
+            println!("{}", group);
+            for example in &group.examples {
+                println!("  {}", example);
+            }
+        }
+    }
+
+    if result.truncated {
+        println!("\nerror limit reached, further errors were not reported");
     }
 
     if result.halted {
         println!("\nunable to parse any further");
-        process::exit(1);
+        return 1;
+    }
+
+    2
+}
+
+/// Validates the files, then re-validates them whenever they change, printing
+/// the errors that appeared and disappeared; runs until interrupted
+#[cfg(feature = "watch")]
+fn watch(
+    args: &Args,
+    options: &ValidationOptions,
+    schema: Option<&Schema>,
+    baseline: Option<&Baseline>,
+    jobs: usize,
+) -> i32 {
+    let roots: Vec<PathBuf> = args.files.iter().map(PathBuf::from).collect();
+    let watcher = match FileWatcher::new(&roots) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("error watching files: {}", e);
+            return 1;
+        }
+    };
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let directories: Vec<PathBuf> = roots
+        .iter()
+        .filter(|root| root.is_dir())
+        .map(|root| absolute(root))
+        .collect();
+
+    let mut files = Vec::new();
+    for root in &roots {
+        if !root.is_dir() {
+            files.push(root.clone());
+            continue;
+        }
+        match csv_files(root) {
+            Ok(found) => files.extend(found),
+            Err(e) => {
+                eprintln!("error reading directory '{}': {}", root.display(), e);
+                return 1;
+            }
+        }
+    }
+
+    let check = |path: &Path| {
+        let options = file_options(path, options, schema)?;
+        let mut result = check_file(path, false, &options, jobs)?;
+        if let Some(baseline) = baseline {
+            let file = path.display().to_string();
+            let errors = std::mem::take(&mut result.errors);
+            match with_content(path, false, |content| {
+                baseline.apply(&file, errors, content, &options)
+            }) {
+                Ok(outcome) => result.errors = outcome.errors,
+                Err(e) => {
+                    eprintln!("error reading file '{}': {}", file, e);
+                    return None;
+                }
+            }
+        }
+        Some(result)
+    };
+
+    // The files and their errors as of the last run, by absolute path
+    let mut previous: BTreeMap<PathBuf, (PathBuf, Vec<CsvError>)> = BTreeMap::new();
+    for (i, file) in files.into_iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("==> {} <==", file.display());
+        let errors = match check(&file) {
+            Some(result) => {
+                report(&result, args, options);
+                result.errors
+            }
+            None => Vec::new(),
+        };
+        previous.insert(absolute(&file), (file, errors));
+    }
+    println!("\nwatching for changes, press Ctrl-C to stop");
+
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    while let Some(changes) = watcher.next_changes() {
+        let changed = match changes {
+            Ok(changed) => changed,
+            Err(e) => {
+                eprintln!("error watching files: {}", e);
+                return 1;
+            }
+        };
+
+        for path in changed {
+            let (file, errors) = match previous.remove(&path) {
+                Some(entry) => entry,
+                // A new file in a watched directory
+                None if is_csv_file(&path)
+                    && directories.iter().any(|dir| path.starts_with(dir)) =>
+                {
+                    let file = path
+                        .strip_prefix(&current_dir)
+                        .unwrap_or(&path)
+                        .to_path_buf();
+                    (file, Vec::new())
+                }
+                None => continue,
+            };
+            println!("\n==> {} <==", file.display());
+
+            let result = if file.exists() {
+                check(&file)
+            } else {
+                println!("file removed");
+                None
+            };
+            let Some(result) = result else {
+                previous.insert(path, (file, errors));
+                continue;
+            };
+
+            let diff = diff_errors(&errors, &result.errors);
+            for error in &diff.added {
+                println!("+ {}", error);
+            }
+            for error in &diff.fixed {
+                println!("- {}", error);
+            }
+            if result.errors.is_empty() {
+                println!("{} fixed, file is valid", diff.fixed.len());
+            } else {
+                println!(
+                    "{} new, {} fixed, {} error(s) remaining",
+                    diff.added.len(),
+                    diff.fixed.len(),
+                    result.errors.len()
+                );
+            }
+            previous.insert(path, (file, result.errors));
+        }
+    }
+    0
+}
+
+/// Profiles one file and prints its statistics, returning the exit code
+fn stats(args: &StatsArgs) -> i32 {
+    let options = dialect_options(&args.dialect);
+    let stats_options = StatsOptions {
+        top_k: args.top,
+        approximate: args.approximate,
+    };
+
+    let path = Path::new(&args.file);
+    let input = match File::open(path).and_then(open_input) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("error reading file '{}': {}", args.file, e);
+            return 1;
+        }
+    };
+    let result = match input {
+        #[cfg(feature = "mmap")]
+        Input::Mapped(map) => profile(&map[..], &options, &stats_options),
+        Input::Stream(reader) => profile(reader, &options, &stats_options),
+    };
+
+    match result {
+        Ok(profile) if args.json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&profile).expect("statistics serialize to JSON")
+            );
+            0
+        }
+        Ok(profile) => {
+            print!("{}", profile);
+            0
+        }
+        Err(e) => {
+            eprintln!("error profiling file '{}': {}", args.file, e);
+            1
+        }
+    }
+}
+
+/// Serves diagnostics to an editor until it shuts the server down, returning the exit code
+#[cfg(feature = "lsp")]
+fn lsp(args: &LspArgs) -> i32 {
+    let mut options = dialect_options(&args.dialect);
+    rule_options(&mut options, &args.rules);
+    match csvlint::lsp::serve(&options) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("language server error: {}", e);
+            1
+        }
+    }
+}
+
+/// Input data, either mapped into memory or streamed through a reader
+enum Input {
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+    Stream(Box<dyn Read>),
+}
+
+/// Maps uncompressed regular files, and streams everything else
+fn open_input(file: File) -> io::Result<Input> {
+    #[cfg(feature = "mmap")]
+    if let Some(map) = mmap::map_file(&file)?
+        && Compression::detect(&map) == Compression::None
+    {
+        return Ok(Input::Mapped(map));
+    }
+
+    // Transparently decompress gzip/zstd/bzip2/xz inputs
+    let (_, reader) = decompress(file)?;
+    Ok(Input::Stream(reader))
+}
+
+/// Streams the content of the file at `path` that is staged in the git index
+fn open_staged(path: &Path) -> io::Result<Input> {
+    let blob = staged_file(path)
+        .map_err(io::Error::other)?
+        .ok_or_else(|| io::Error::other("not staged in the git index, `git add` it first"))?;
+    let (_, reader) = decompress(io::Cursor::new(blob))?;
+    Ok(Input::Stream(reader))
+}
+
+fn validate_input(
+    input: Input,
+    jobs: usize,
+    options: &ValidationOptions,
+) -> Result<ValidationResult, Box<dyn std::error::Error>> {
+    match input {
+        #[cfg(feature = "mmap")]
+        Input::Mapped(map) => validate_content(&map, jobs, options),
+        Input::Stream(mut reader) if jobs != 1 => {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            validate_content(&content, jobs, options)
+        }
+        Input::Stream(reader) => validate_with_options(reader, options),
+    }
+}
+
+/// Writes a copy of the file at `path` with its fixable errors corrected to `output`
+fn fix_file(path: &Path, output: &Path, options: &ValidationOptions) -> io::Result<usize> {
+    let result = with_content(path, false, |content| fix_slice(content, options))?;
+    fs::write(output, result.content)?;
+    Ok(result.fixes)
+}
+
+/// Reads the whole (decompressed) content of the file at `path`, or with
+/// `staged` its content in the git index, and passes it to `f`
+fn with_content<T>(path: &Path, staged: bool, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+    let input = if staged {
+        open_staged(path)?
+    } else {
+        open_input(File::open(path)?)?
+    };
+    match input {
+        #[cfg(feature = "mmap")]
+        Input::Mapped(map) => Ok(f(&map)),
+        Input::Stream(mut reader) => {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            Ok(f(&content))
+        }
     }
+}
 
-    process::exit(2);
+/// Validates in-memory content, in parallel if requested
+fn validate_content(
+    content: &[u8],
+    jobs: usize,
+    options: &ValidationOptions,
+) -> Result<ValidationResult, Box<dyn std::error::Error>> {
+    #[cfg(feature = "parallel")]
+    if jobs != 1 {
+        return validate_parallel_with_options(content, options, jobs);
+    }
+    #[cfg(not(feature = "parallel"))]
+    debug_assert_eq!(
+        jobs, 1,
+        "multi-threaded validation needs the `parallel` feature"
+    );
+
+    validate_slice_with_options(content, options)
 }
 
-fn parse_delimiter(delimiter_str: &str) -> Result<u8, String> {
+/// Parses a delimiter into its bytes
+///
+/// Delimiters longer than one byte, such as `||` or `¦`, can't contain
+/// quotes or line breaks.
+fn parse_delimiter(delimiter_str: &str) -> Result<Vec<u8>, String> {
     match delimiter_str {
-        "," => Ok(b','),
-        "\\t" => Ok(b'\t'),
-        "|" => Ok(b'|'),
-        ":" => Ok(b':'),
-        ";" => Ok(b';'),
-        s if s.len() == 1 => Ok(s.as_bytes()[0]),
-        _ => Err(format!(
-            "error parsing delimiter '{}', note that only one-character delimiters are supported",
+        "\\t" => Ok(vec![b'\t']),
+        "" => Err("error parsing delimiter: the delimiter can't be empty".to_string()),
+        s if s.len() == 1 => Ok(s.as_bytes().to_vec()),
+        s if s.contains(['"', '\r', '\n']) => Err(format!(
+            "error parsing delimiter '{}', note that multi-character delimiters can't contain quotes or line breaks",
             delimiter_str
         )),
+        s => Ok(s.as_bytes().to_vec()),
+    }
+}
+
+/// Parses the regular expression of `--delimiter-regex`
+fn parse_delimiter_regex(pattern: &str) -> Result<Delimiter, String> {
+    Delimiter::pattern(pattern).map_err(|e| e.to_string())
+}
+
+/// Parses a per-kind error limit such as `InvalidLineEnding=100`
+fn parse_kind_limit(spec: &str) -> Result<(String, usize), String> {
+    let (kind, limit) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected KIND=N, got '{}'", spec))?;
+    if !CsvErrorKind::NAMES.contains(&kind) {
+        return Err(format!(
+            "unknown error kind '{}', expected one of: {}",
+            kind,
+            CsvErrorKind::NAMES.join(", ")
+        ));
+    }
+    let limit = limit
+        .parse()
+        .map_err(|_| format!("invalid limit '{}' for {}", limit, kind))?;
+    Ok((kind.to_string(), limit))
+}
+
+/// Parses a sample spec: a row count such as `1000` or a percentage such as `5%`
+fn parse_sample(spec: &str) -> Result<Sample, String> {
+    if let Some(percent) = spec.strip_suffix('%') {
+        let percent: f64 = percent
+            .parse()
+            .map_err(|_| format!("invalid sample percentage '{}'", spec))?;
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(format!(
+                "sample percentage must be between 0 and 100, got '{}'",
+                spec
+            ));
+        }
+        Ok(Sample::Percent { percent, seed: 0 })
+    } else {
+        spec.parse().map(Sample::FirstRows).map_err(|_| {
+            format!(
+                "invalid sample '{}', expected a row count or a percentage",
+                spec
+            )
+        })
+    }
+}
+
+fn parse_comment(comment: &str) -> Result<u8, String> {
+    match comment.as_bytes() {
+        [byte] if byte.is_ascii_graphic() && *byte != b'"' => Ok(*byte),
+        _ => Err(format!(
+            "invalid comment character '{}', expected one printable ASCII character other than '\"'",
+            comment
+        )),
+    }
+}
+
+fn parse_header_row(row: &str) -> Result<usize, String> {
+    match row.parse() {
+        Ok(row) if row >= 1 => Ok(row),
+        _ => Err(format!(
+            "invalid header row '{}', expected a line number from 1",
+            row
+        )),
+    }
+}
+
+fn parse_footer(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| e.to_string())
+}
+
+fn parse_final_newline(rule: &str) -> Result<FinalNewline, String> {
+    match rule {
+        "require" => Ok(FinalNewline::Require),
+        "forbid" => Ok(FinalNewline::Forbid),
+        _ => Err(format!(
+            "invalid final newline rule '{}', expected 'require' or 'forbid'",
+            rule
+        )),
+    }
+}
+
+fn parse_field_scope(fields: &str) -> Result<FieldScope, String> {
+    match fields {
+        "unquoted" => Ok(FieldScope::Unquoted),
+        "quoted" => Ok(FieldScope::Quoted),
+        "all" => Ok(FieldScope::All),
+        _ => Err(format!(
+            "invalid fields '{}', expected 'unquoted', 'quoted' or 'all'",
+            fields
+        )),
     }
 }
 
+/// Parses a field length limit for every column ("255") or for one ("name=255")
+fn parse_field_length(spec: &str) -> Result<(Option<String>, usize), String> {
+    let (column, max) = match spec.rsplit_once('=') {
+        Some((column, max)) => (Some(column.to_string()), max),
+        None => (None, spec),
+    };
+    let max = max
+        .parse()
+        .map_err(|_| format!("invalid length '{}', expected e.g. 255 or name=255", spec))?;
+    Ok((column, max))
+}
+
+/// Parses a code point ("U+200D") or an inclusive range of them ("U+0000-U+001F")
+fn parse_char_range(spec: &str) -> Result<RangeInclusive<u32>, String> {
+    let parse = |code_point: &str| {
+        code_point
+            .strip_prefix("U+")
+            .or_else(|| code_point.strip_prefix("u+"))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("invalid code point '{}', expected e.g. U+200D", code_point))
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(spec)?, parse(spec)?),
+    };
+    if start > end {
+        return Err(format!("invalid range '{}'", spec));
+    }
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+mod test_support;
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_support::git;
+
+    #[test]
+    fn test_about() {
+        let command = <Args as clap::CommandFactory>::command();
+        assert_eq!(
+            command.get_about().map(ToString::to_string).as_deref(),
+            Some("A CSV linter that validates CSV files according to RFC 4180")
+        );
+    }
 
     #[test]
     fn test_parse_delimiter() {
-        assert_eq!(parse_delimiter(",").unwrap(), b',');
-        assert_eq!(parse_delimiter("\\t").unwrap(), b'\t');
-        assert_eq!(parse_delimiter("|").unwrap(), b'|');
-        assert_eq!(parse_delimiter(":").unwrap(), b':');
-        assert_eq!(parse_delimiter(";").unwrap(), b';');
-        assert_eq!(parse_delimiter("x").unwrap(), b'x');
+        assert_eq!(parse_delimiter(",").unwrap(), b",");
+        assert_eq!(parse_delimiter("\\t").unwrap(), b"\t");
+        assert_eq!(parse_delimiter("|").unwrap(), b"|");
+        assert_eq!(parse_delimiter(":").unwrap(), b":");
+        assert_eq!(parse_delimiter(";").unwrap(), b";");
+        assert_eq!(parse_delimiter("x").unwrap(), b"x");
+        assert_eq!(parse_delimiter("||").unwrap(), b"||");
+        assert_eq!(parse_delimiter("~|~").unwrap(), b"~|~");
+        assert_eq!(parse_delimiter("¦").unwrap(), "¦".as_bytes());
+        assert_eq!(parse_delimiter("→").unwrap(), "→".as_bytes());
 
         assert!(parse_delimiter("").is_err());
-        assert!(parse_delimiter("ab").is_err());
+        assert!(parse_delimiter("\"|").is_err());
+        assert!(parse_delimiter("|\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_delimiter_regex() {
+        assert!(matches!(
+            parse_delimiter_regex(r"\s*;\s*"),
+            Ok(Delimiter::Pattern(_))
+        ));
+        assert!(parse_delimiter_regex("(").is_err());
+    }
+
+    #[test]
+    fn test_parse_kind_limit() {
+        assert_eq!(
+            parse_kind_limit("InvalidLineEnding=100").unwrap(),
+            ("InvalidLineEnding".to_string(), 100)
+        );
+        assert!(parse_kind_limit("InvalidLineEnding").is_err());
+        assert!(parse_kind_limit("NoSuchKind=1").is_err());
+        assert!(parse_kind_limit("FieldCount=x").is_err());
+    }
+
+    #[test]
+    fn test_parse_sample() {
+        assert_eq!(parse_sample("1000").unwrap(), Sample::FirstRows(1000));
+        assert_eq!(
+            parse_sample("2.5%").unwrap(),
+            Sample::Percent {
+                percent: 2.5,
+                seed: 0
+            }
+        );
+        assert!(parse_sample("150%").is_err());
+        assert!(parse_sample("lots").is_err());
+    }
+
+    #[test]
+    fn test_parse_comment() {
+        assert_eq!(parse_comment("#").unwrap(), b'#');
+        assert_eq!(parse_comment(";").unwrap(), b';');
+        assert!(parse_comment("").is_err());
+        assert!(parse_comment("//").is_err());
+        assert!(parse_comment("\"").is_err());
+        assert!(parse_comment(" ").is_err());
+    }
+
+    #[test]
+    fn test_parse_header_row() {
+        assert_eq!(parse_header_row("1").unwrap(), 1);
+        assert_eq!(parse_header_row("4").unwrap(), 4);
+        assert!(parse_header_row("0").is_err());
+        assert!(parse_header_row("x").is_err());
+    }
+
+    #[test]
+    fn test_parse_footer() {
+        assert!(parse_footer("^Total:").unwrap().is_match(b"Total:,5"));
+        assert!(parse_footer("(").is_err());
+    }
+
+    #[test]
+    fn test_parse_final_newline() {
+        assert_eq!(
+            parse_final_newline("require").unwrap(),
+            FinalNewline::Require
+        );
+        assert_eq!(parse_final_newline("forbid").unwrap(), FinalNewline::Forbid);
+        assert!(parse_final_newline("maybe").is_err());
+    }
+
+    #[test]
+    fn test_parse_char_range() {
+        assert_eq!(parse_char_range("U+200D").unwrap(), 0x200d..=0x200d);
+        assert_eq!(parse_char_range("U+0000-U+001F").unwrap(), 0..=0x1f);
+        assert!(parse_char_range("200D").is_err());
+        assert!(parse_char_range("U+20-U+10").is_err());
+    }
+
+    #[test]
+    fn test_parse_field_length() {
+        assert_eq!(parse_field_length("255").unwrap(), (None, 255));
+        assert_eq!(
+            parse_field_length("a=b=10").unwrap(),
+            (Some("a=b".to_string()), 10)
+        );
+        assert!(parse_field_length("name=").is_err());
+    }
+
+    #[test]
+    fn test_worst_exit_code() {
+        assert_eq!(worst_exit_code(0, 2), 2);
+        assert_eq!(worst_exit_code(2, 0), 2);
+        assert_eq!(worst_exit_code(2, 1), 1);
+        assert_eq!(worst_exit_code(1, 2), 1);
+    }
+
+    #[test]
+    fn test_parse_field_scope() {
+        assert_eq!(parse_field_scope("unquoted").unwrap(), FieldScope::Unquoted);
+        assert_eq!(parse_field_scope("all").unwrap(), FieldScope::All);
+        assert!(parse_field_scope("some").is_err());
+    }
+
+    #[test]
+    fn test_staged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        git(dir.path(), &["init", "--quiet"]);
+        fs::write(&path, "a,b\n1,2\n3\n").unwrap();
+        git(dir.path(), &["add", "data.csv"]);
+        fs::write(&path, "a,b\n1,2\n3,4\n").unwrap();
+
+        // The bad row is only in the index
+        let options = ValidationOptions::default();
+        let working = check_file(&path, false, &options, 1).unwrap();
+        assert!(working.errors.is_empty());
+        let staged = check_file(&path, true, &options, 1).unwrap();
+        let reported: Vec<String> = staged.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(reported.len(), 1);
+        assert!(
+            reported[0].starts_with("Record #2 has error"),
+            "{}",
+            reported[0]
+        );
+        assert_eq!(
+            with_content(&path, true, <[u8]>::to_vec).unwrap(),
+            b"a,b\n1,2\n3\n"
+        );
+
+        // Untracked files and files outside a repository can't be read from the index
+        let untracked = dir.path().join("new.csv");
+        fs::write(&untracked, "a,b\n").unwrap();
+        assert!(check_file(&untracked, true, &options, 1).is_none());
+        let error = with_content(&untracked, true, |_| ()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "not staged in the git index, `git add` it first"
+        );
+        let outside = tempfile::tempdir().unwrap();
+        let path = outside.path().join("data.csv");
+        fs::write(&path, "a,b\n").unwrap();
+        let error = with_content(&path, true, |_| ()).unwrap_err();
+        assert!(
+            error.to_string().contains("not a git repository"),
+            "{}",
+            error
+        );
     }
 }
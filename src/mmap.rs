@@ -0,0 +1,53 @@
+//! Memory-mapped input for regular files
+//!
+//! Mapping a file lets the line-ending checker and the tokenizer scan it in
+//! place instead of copying it into a buffer first. Pipes, sockets and other
+//! non-regular files cannot be mapped and should be streamed instead.
+
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+
+/// Maps `file` into memory if it is a non-empty regular file
+///
+/// Returns `Ok(None)` when the file cannot be mapped and the caller should fall
+/// back to reading it as a stream.
+pub fn map_file(file: &File) -> io::Result<Option<Mmap>> {
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Ok(None);
+    }
+
+    // SAFETY: the mapping is only read. If another process truncates the file
+    // while it is mapped, reads may fault; this is the usual trade-off accepted
+    // by tools that mmap their inputs.
+    match unsafe { Mmap::map(file) } {
+        Ok(map) => {
+            #[cfg(unix)]
+            let _ = map.advise(memmap2::Advice::Sequential);
+            Ok(Some(map))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_map_regular_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"a,b\r\n1,2\r\n").unwrap();
+
+        let map = map_file(file.as_file()).unwrap().unwrap();
+        assert_eq!(&map[..], b"a,b\r\n1,2\r\n");
+    }
+
+    #[test]
+    fn test_empty_file_is_not_mapped() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert!(map_file(file.as_file()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,196 @@
+//! Options controlling how a file is validated
+
+use crate::blank_lines::FinalNewline;
+use crate::characters::default_allowed_characters;
+use crate::fixed_width::FixedWidthSpec;
+use crate::schema::ForeignKeyCheck;
+use crate::size_limits::SizeLimits;
+use crate::skip::SkipLines;
+use crate::tokenizer::Delimiter;
+use crate::unique::UniqueMode;
+use crate::whitespace::FieldScope;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Options controlling validation
+#[derive(Debug, Clone)]
+pub struct ValidationOptions {
+    /// The field delimiter character (e.g., ',', '\t', '|')
+    pub delimiter: u8,
+    /// A delimiter the csv crate can't split on, such as `||` or `¦`, read by
+    /// the [`tokenizer`](crate::tokenizer); when set, `delimiter` is ignored
+    pub custom_delimiter: Option<Delimiter>,
+    /// Whether to attempt parsing lines that aren't quoted properly
+    pub lazy_quotes: bool,
+    /// Lines starting with this byte at the start of a record are comments, which
+    /// aren't records; line numbers still count them
+    pub comment: Option<u8>,
+    /// Lines skipped before the header and at the end of the file
+    pub skip: SkipLines,
+    /// Columns of a fixed-width file; when set, the file is validated as one
+    /// instead of as CSV, see [`crate::fixed_width`]
+    pub fixed_width: Option<FixedWidthSpec>,
+    /// Whether to enforce strict RFC 4180 rules such as CRLF line endings
+    pub rfc4180_mode: bool,
+    /// Whether to flag lines whose terminator differs from the file's dominant one
+    pub mixed_line_endings: bool,
+    /// Whether the last line must, or must not, end with a line break
+    pub final_newline: FinalNewline,
+    /// Whether to flag empty lines between records, which the csv crate skips
+    pub blank_lines: bool,
+    /// Whether to flag empty lines at the end of the file
+    pub trailing_blank_lines: bool,
+    /// Which fields are checked for leading and trailing whitespace
+    pub surrounding_whitespace: FieldScope,
+    /// Whether to flag non-breaking spaces inside fields
+    pub non_breaking_spaces: bool,
+    /// Whether to flag tabs inside fields, unless tab is the delimiter
+    pub tabs: bool,
+    /// Whether to flag fields a spreadsheet could evaluate as formulas
+    pub formula_injection: bool,
+    /// Whether to flag NUL bytes, control characters, invisible characters,
+    /// bidi controls and lone surrogates inside fields
+    pub characters: bool,
+    /// Code points never reported by the character rules; tab, LF and CR by default
+    pub allowed_characters: Vec<RangeInclusive<u32>>,
+    /// Whether to flag records identical to an earlier one
+    pub unique_rows: bool,
+    /// Names of columns whose combined values must be unique
+    pub unique_key: Vec<String>,
+    /// How rows are remembered for the uniqueness checks
+    pub unique_mode: UniqueMode,
+    /// Foreign keys whose values must exist in another file, see [`crate::schema`]
+    pub foreign_keys: Vec<ForeignKeyCheck>,
+    /// Limits on field length, record size and record count
+    pub size_limits: SizeLimits,
+    /// Limits on how many errors are collected
+    pub limits: ErrorLimits,
+    /// Which records are checked
+    pub sample: Sample,
+}
+
+impl ValidationOptions {
+    /// Creates options for the given dialect, with no limits or sampling
+    pub fn new(delimiter: u8, lazy_quotes: bool, rfc4180_mode: bool) -> Self {
+        ValidationOptions {
+            delimiter,
+            lazy_quotes,
+            rfc4180_mode,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a rule looks at the values of individual fields
+    pub(crate) fn has_field_rules(&self) -> bool {
+        crate::whitespace::enabled(self)
+            || self.formula_injection
+            || self.characters
+            || !self.foreign_keys.is_empty()
+            || self.size_limits.is_enabled()
+            || self.has_record_state()
+    }
+
+    /// Whether a rule compares records with each other, which rules out splitting the file
+    pub(crate) fn has_record_state(&self) -> bool {
+        self.unique_rows || !self.unique_key.is_empty()
+    }
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions {
+            delimiter: b',',
+            custom_delimiter: None,
+            lazy_quotes: false,
+            comment: None,
+            skip: SkipLines::default(),
+            fixed_width: None,
+            rfc4180_mode: false,
+            mixed_line_endings: true,
+            final_newline: FinalNewline::Any,
+            blank_lines: false,
+            trailing_blank_lines: false,
+            surrounding_whitespace: FieldScope::None,
+            non_breaking_spaces: false,
+            tabs: false,
+            formula_injection: false,
+            characters: false,
+            allowed_characters: default_allowed_characters(),
+            unique_rows: false,
+            unique_key: Vec::new(),
+            unique_mode: UniqueMode::Exact,
+            foreign_keys: Vec::new(),
+            size_limits: SizeLimits::default(),
+            limits: ErrorLimits::default(),
+            sample: Sample::All,
+        }
+    }
+}
+
+/// Limits on the number of errors collected
+///
+/// Once a limit is hit, further errors are dropped and the result is marked
+/// as truncated.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorLimits {
+    /// Stop validating once this many errors have been collected
+    pub max_errors: Option<usize>,
+    /// Maximum number of errors kept per error kind, keyed by [`CsvErrorKind::name`]
+    ///
+    /// [`CsvErrorKind::name`]: crate::CsvErrorKind::name
+    pub max_per_kind: HashMap<String, usize>,
+}
+
+/// Which records are checked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sample {
+    /// Check every record
+    All,
+    /// Check only the header and the first N data records
+    FirstRows(usize),
+    /// Check a pseudo-random percentage of records, chosen deterministically from `seed`
+    Percent { percent: f64, seed: u64 },
+}
+
+impl Sample {
+    /// Whether the record (or line) with the given number is part of the sample
+    pub fn includes(&self, num: usize) -> bool {
+        match *self {
+            Sample::All | Sample::FirstRows(_) => true,
+            Sample::Percent { percent, seed } => {
+                // Hashing the number instead of drawing from a sequential RNG keeps
+                // the selection identical between sequential and parallel runs
+                let hash = splitmix64(seed ^ num as u64);
+                (hash as f64 / u64::MAX as f64) * 100.0 < percent
+            }
+        }
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_sample() {
+        let sample = Sample::Percent {
+            percent: 10.0,
+            seed: 42,
+        };
+        let selected = (0..100_000).filter(|&n| sample.includes(n)).count();
+        assert!((9_000..11_000).contains(&selected), "selected {}", selected);
+
+        // The selection is deterministic for a given seed
+        assert_eq!(
+            (0..100).map(|n| sample.includes(n)).collect::<Vec<_>>(),
+            (0..100).map(|n| sample.includes(n)).collect::<Vec<_>>()
+        );
+    }
+}
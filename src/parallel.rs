@@ -0,0 +1,463 @@
+//! Parallel chunked validation for large inputs
+//!
+//! The input is cut into roughly equal chunks which are then moved forward to
+//! the next record boundary. Because a chunk may start in the middle of a quoted
+//! field, every chunk is first scanned speculatively from each possible parser
+//! state; the real state at each chunk start is then resolved by stitching
+//! these tables together in file order. The resulting record-aligned slices are
+//! validated on a thread pool and the errors merged back in file order.
+
+use crate::blank_lines::validate_blank_lines;
+use crate::collector::ErrorCollector;
+use crate::line_endings::{
+    LineEndingStats, count_line_breaks, expected_line_ending, validate_line_endings,
+};
+use crate::size_limits::validate_record_count;
+use crate::{
+    CsvError, ErrorLimits, Sample, ValidationOptions, ValidationResult, csv_reader_builder,
+    read_header, record_end, sampled_content, skip, validate_records, validate_slice_with_options,
+};
+use rayon::prelude::*;
+use std::io::{Cursor, SeekFrom};
+
+/// Smallest chunk worth handing to a worker thread
+const MIN_CHUNK_SIZE: usize = 1 << 20;
+
+/// Number of chunks created per worker thread, to even out uneven chunks
+const CHUNKS_PER_JOB: usize = 4;
+
+/// Quote state of the tokenizer at a given byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    /// At the start of a field (or record)
+    FieldStart,
+    /// Inside an unquoted field
+    Unquoted,
+    /// Inside a quoted field
+    Quoted,
+    /// Just saw a quote inside a quoted field; either an escape or the closing quote
+    QuoteInQuoted,
+}
+
+const SCAN_STATES: [ScanState; 4] = [
+    ScanState::FieldStart,
+    ScanState::Unquoted,
+    ScanState::Quoted,
+    ScanState::QuoteInQuoted,
+];
+
+impl ScanState {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Result of speculatively scanning one chunk from a given start state
+#[derive(Debug, Clone, Copy)]
+struct Speculation {
+    /// Offset just past the first record terminator in the chunk, if any
+    first_boundary: Option<usize>,
+    /// Scanner state after the last byte of the chunk
+    end_state: ScanState,
+}
+
+/// Scans `chunk` from `state`, mirroring the quoting rules of the csv crate
+fn speculate(chunk: &[u8], mut state: ScanState, delimiter: u8, quoting: bool) -> Speculation {
+    let mut first_boundary = None;
+
+    for (i, &byte) in chunk.iter().enumerate() {
+        state = match state {
+            ScanState::Quoted => {
+                if byte == b'"' {
+                    ScanState::QuoteInQuoted
+                } else {
+                    ScanState::Quoted
+                }
+            }
+            ScanState::QuoteInQuoted if byte == b'"' => ScanState::Quoted,
+            ScanState::FieldStart if quoting && byte == b'"' => ScanState::Quoted,
+            _ => {
+                if byte == b'\n' {
+                    if first_boundary.is_none() {
+                        first_boundary = Some(i + 1);
+                    }
+                    ScanState::FieldStart
+                } else if byte == delimiter || byte == b'\r' {
+                    ScanState::FieldStart
+                } else {
+                    ScanState::Unquoted
+                }
+            }
+        };
+    }
+
+    Speculation {
+        first_boundary,
+        end_state: state,
+    }
+}
+
+/// Splits `data` into record-aligned slices, returned as start offsets
+fn record_boundaries(data: &[u8], delimiter: u8, quoting: bool, chunk_size: usize) -> Vec<usize> {
+    let chunk_size = chunk_size.max(1);
+    let chunks: Vec<&[u8]> = data.chunks(chunk_size).collect();
+
+    // Speculatively scan every chunk from every possible start state
+    let tables: Vec<[Speculation; 4]> = chunks
+        .par_iter()
+        .map(|chunk| SCAN_STATES.map(|state| speculate(chunk, state, delimiter, quoting)))
+        .collect();
+
+    // Resolve the actual state at each chunk start and keep the matching boundary
+    let mut boundaries = vec![0];
+    let mut state = ScanState::FieldStart;
+    for (i, table) in tables.iter().enumerate() {
+        let speculation = table[state.index()];
+        if i > 0
+            && let Some(offset) = speculation.first_boundary
+        {
+            let boundary = i * chunk_size + offset;
+            if boundary < data.len() {
+                boundaries.push(boundary);
+            }
+        }
+        state = speculation.end_state;
+    }
+
+    boundaries
+}
+
+/// Validates CSV data in memory using multiple threads
+///
+/// Produces the same errors, in the same order and with the same record
+/// numbers, as [`validate_slice`]. Inputs too small to benefit from
+/// parallelism are validated sequentially.
+///
+/// # Arguments
+/// * `content` - The CSV data
+/// * `delimiter` - The field delimiter character
+/// * `lazy_quotes` - Whether to attempt parsing lines that aren't quoted properly
+/// * `rfc4180_mode` - Whether to enforce CRLF line endings
+/// * `jobs` - Number of worker threads, or 0 to use all available cores
+///
+/// [`validate_slice`]: crate::validate_slice
+pub fn validate_parallel(
+    content: &[u8],
+    delimiter: u8,
+    lazy_quotes: bool,
+    rfc4180_mode: bool,
+    jobs: usize,
+) -> Result<ValidationResult, Box<dyn std::error::Error>> {
+    let options = ValidationOptions::new(delimiter, lazy_quotes, rfc4180_mode);
+    validate_parallel_with_options(content, &options, jobs)
+}
+
+/// Validates CSV data in memory using multiple threads and the given options
+pub fn validate_parallel_with_options(
+    content: &[u8],
+    options: &ValidationOptions,
+    jobs: usize,
+) -> Result<ValidationResult, Box<dyn std::error::Error>> {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    let jobs = pool.current_num_threads();
+    let data = skip::data(content, options);
+    let sampled = sampled_content(&content[data.range], options);
+    let chunk_size = (sampled.len() / (jobs * CHUNKS_PER_JOB)).max(MIN_CHUNK_SIZE);
+
+    // Oversized records are skipped by the sequential path before they reach a parser,
+    // comment lines would hide quotes from the speculative boundary scan, and chunks
+    // are parsed by the csv crate, which can't split on a custom delimiter or read
+    // fixed-width lines
+    if jobs == 1
+        || sampled.len() <= chunk_size
+        || options.has_record_state()
+        || options.size_limits.max_record_bytes.is_some()
+        || options.comment.is_some()
+        || options.custom_delimiter.is_some()
+        || options.fixed_width.is_some()
+    {
+        return validate_slice_with_options(content, options);
+    }
+
+    Ok(pool.install(|| validate_chunked(sampled, data.first_line, options, chunk_size)))
+}
+
+/// Errors found in a single record-aligned slice
+struct SliceResult {
+    start: usize,
+    line_ending_errors: Vec<CsvError>,
+    lines: usize,
+    /// Number of LFs, by which the csv crate counts lines
+    csv_lines: usize,
+    record_errors: Vec<CsvError>,
+    records: usize,
+    stopped: bool,
+    halted: bool,
+}
+
+/// Validates `content`, whose first line is numbered `first_line`, in record-aligned slices
+fn validate_chunked(
+    content: &[u8],
+    first_line: usize,
+    options: &ValidationOptions,
+    chunk_size: usize,
+) -> ValidationResult {
+    let mut errors = ErrorCollector::new(&options.limits);
+
+    // The dominant line ending is a property of the whole file, so it is found before splitting
+    let line_endings = LineEndingStats::from_content(content, options);
+    let expected = expected_line_ending(&line_endings, options);
+
+    // The header is read up front; its field count is needed by every slice
+    let mut header_errors = ErrorCollector::new(&options.limits);
+    let mut csv_reader = csv_reader_builder(options).from_reader(content);
+    let header_result = read_header(&mut csv_reader, options, &mut header_errors);
+    let data_start = record_end(content, csv_reader.position());
+    let (header_errors, _) = header_errors.into_parts();
+
+    let header = match header_result {
+        Ok(header) => header,
+        Err(()) => {
+            validate_line_endings(content, first_line, options, expected, &mut errors);
+            validate_blank_lines(content, first_line, options, &mut errors);
+            header_errors.into_iter().for_each(|e| errors.push(e));
+            return ValidationResult::from_collector(errors, true, line_endings);
+        }
+    };
+
+    let header_bytes = &content[..data_start];
+    let data = &content[data_start..];
+    let mut starts = record_boundaries(data, options.delimiter, !options.lazy_quotes, chunk_size);
+    starts.push(data.len());
+
+    // Slices only know relative record and line numbers, so a percentage sample
+    // is applied while merging; the slices then collect without limits so that
+    // no sampled error is dropped early
+    let slice_options = match options.sample {
+        Sample::Percent { .. } => ValidationOptions {
+            sample: Sample::All,
+            limits: ErrorLimits::default(),
+            ..options.clone()
+        },
+        _ => options.clone(),
+    };
+
+    let results: Vec<SliceResult> = starts
+        .par_windows(2)
+        .map(|window| {
+            let slice = &data[window[0]..window[1]];
+            let lines = count_line_breaks(slice);
+            let csv_lines = slice.iter().filter(|&&b| b == b'\n').count();
+
+            // Each slice collects under the same limits, which bounds its memory use;
+            // the merged result below applies the limits again in file order
+            let mut line_ending_errors = ErrorCollector::new(&slice_options.limits);
+            validate_line_endings(slice, 0, &slice_options, expected, &mut line_ending_errors);
+
+            let mut record_errors = ErrorCollector::new(&slice_options.limits);
+            let mut csv_reader = csv_reader_builder(options).from_reader(slice);
+            let outcome = validate_records(
+                &mut csv_reader,
+                slice,
+                header.as_ref(),
+                0,
+                &slice_options,
+                &mut record_errors,
+            );
+
+            SliceResult {
+                start: window[0],
+                line_ending_errors: line_ending_errors.into_parts().0,
+                lines,
+                csv_lines,
+                record_errors: record_errors.into_parts().0,
+                records: outcome.records,
+                stopped: outcome.stopped,
+                halted: outcome.halted,
+            }
+        })
+        .collect();
+
+    // Line ending errors cover the whole file and come first, as in the sequential path
+    let header_lines = count_line_breaks(header_bytes);
+    if options.rfc4180_mode || expected.is_some() {
+        validate_line_endings(header_bytes, first_line, options, expected, &mut errors);
+
+        let mut line_offset = first_line + header_lines;
+        for result in &results {
+            for error in &result.line_ending_errors {
+                let record_num = error.record_num + line_offset;
+                if options.sample.includes(record_num) {
+                    errors.push(CsvError {
+                        record_num,
+                        ..error.clone()
+                    });
+                }
+            }
+            line_offset += result.lines;
+        }
+    }
+
+    // Blank lines are only known to be in the middle or at the end with the whole file in view
+    validate_blank_lines(content, first_line, options, &mut errors);
+
+    // Record errors are renumbered and merged until the first slice that stopped parsing
+    let mut record_offset = 0;
+    let mut line_offset = first_line + header_bytes.iter().filter(|&&b| b == b'\n').count();
+    let mut halted = false;
+    for result in results {
+        if result.stopped {
+            // Re-run the failing slice with a positioned reader so that parse
+            // error messages report absolute record, line and byte positions
+            let start = data_start + result.start;
+            let mut position = csv::Position::new();
+            position
+                .set_byte(start as u64)
+                .set_line(line_offset as u64)
+                .set_record((record_offset + usize::from(header.is_some())) as u64);
+
+            let mut csv_reader = csv_reader_builder(options).from_reader(Cursor::new(content));
+            if csv_reader
+                .seek_raw(SeekFrom::Start(start as u64), position)
+                .is_ok()
+            {
+                let outcome = validate_records(
+                    &mut csv_reader,
+                    content,
+                    header.as_ref(),
+                    record_offset,
+                    options,
+                    &mut errors,
+                );
+                halted = outcome.halted;
+                break;
+            }
+        }
+
+        for error in result.record_errors {
+            let record_num = error.record_num + record_offset;
+            if options.sample.includes(record_num) {
+                errors.push(CsvError {
+                    record_num,
+                    ..error
+                });
+            }
+        }
+        record_offset += result.records;
+        line_offset += result.csv_lines;
+        if result.stopped {
+            halted = result.halted;
+            break;
+        }
+        if errors.is_full() {
+            errors.set_truncated();
+            break;
+        }
+    }
+
+    validate_record_count(record_offset, options, &mut errors);
+    ValidationResult::from_collector(errors, halted, line_endings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FieldScope;
+
+    fn generate(rows: usize) -> String {
+        let mut data = String::from("id,name,notes\r\n");
+        for i in 0..rows {
+            match i % 7 {
+                0 => data.push_str(&format!("{},\"multi\r\nline, \"\"quoted\"\"\",x\r\n", i)),
+                3 => data.push_str(&format!("{},extra,field,here\r\n", i)),
+                5 => data.push_str(&format!("{},lf only,y\n", i)),
+                _ => data.push_str(&format!("{},plain,z\r\n", i)),
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_chunked_matches_sequential() {
+        let data = generate(500);
+        for rfc4180_mode in [true, false] {
+            let options = ValidationOptions::new(b',', false, rfc4180_mode);
+            let expected = validate_slice_with_options(data.as_bytes(), &options).unwrap();
+
+            for chunk_size in [1, 7, 64, 1000] {
+                let result = validate_chunked(data.as_bytes(), 1, &options, chunk_size);
+                assert_eq!(result.errors, expected.errors, "chunk size {}", chunk_size);
+                assert_eq!(result.halted, expected.halted);
+                assert_eq!(result.line_endings, expected.line_endings);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunked_field_rules_match_sequential() {
+        // Quoted fields with surrounding whitespace span slice boundaries
+        let data = generate(500)
+            .replace("plain", " plain")
+            .replace("\"multi", "\" multi");
+        let options = ValidationOptions {
+            surrounding_whitespace: FieldScope::All,
+            ..Default::default()
+        };
+        let expected = validate_slice_with_options(data.as_bytes(), &options).unwrap();
+        assert!(!expected.errors.is_empty());
+
+        for chunk_size in [1, 64, 1000] {
+            let result = validate_chunked(data.as_bytes(), 1, &options, chunk_size);
+            assert_eq!(result.errors, expected.errors, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_chunked_stops_at_first_parse_error() {
+        let mut data = generate(100).into_bytes();
+        data.extend_from_slice(b"\xff,\xfe,\xfd\r\n");
+        data.extend_from_slice(generate(100).as_bytes());
+        let options = ValidationOptions::new(b',', false, false);
+        let expected = validate_slice_with_options(&data, &options).unwrap();
+        let result = validate_chunked(&data, 1, &options, 50);
+
+        assert!(expected.halted);
+        assert_eq!(result.errors, expected.errors);
+        assert_eq!(result.halted, expected.halted);
+    }
+
+    #[test]
+    fn test_chunked_limits_and_sampling_match_sequential() {
+        let data = generate(500);
+        let samples = [
+            Sample::FirstRows(100),
+            Sample::Percent {
+                percent: 30.0,
+                seed: 7,
+            },
+        ];
+
+        for sample in samples {
+            let mut options = ValidationOptions::new(b',', false, true);
+            options.sample = sample;
+            options.limits.max_errors = Some(40);
+            options
+                .limits
+                .max_per_kind
+                .insert("InvalidLineEnding".to_string(), 5);
+
+            let expected = validate_slice_with_options(data.as_bytes(), &options).unwrap();
+            let content = sampled_content(data.as_bytes(), &options);
+            let result = validate_chunked(content, 1, &options, 64);
+            assert_eq!(result.errors, expected.errors, "sample {:?}", sample);
+            assert_eq!(result.truncated, expected.truncated);
+        }
+    }
+
+    #[test]
+    fn test_boundaries_skip_quoted_newlines() {
+        let data = b"\"a\nb\",c\nd,e\n";
+        assert_eq!(record_boundaries(data, b',', true, 2), vec![0, 8]);
+        assert_eq!(record_boundaries(data, b',', false, 2), vec![0, 3, 8]);
+    }
+}
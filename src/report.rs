@@ -0,0 +1,307 @@
+//! Grouping of validation errors for reporting
+//!
+//! Files with systematic problems can produce millions of identical errors.
+//! Instead of one line per occurrence, errors of the same kind, in the same
+//! column, are collapsed into ranges of record (or line) numbers, with a few
+//! examples kept for each.
+
+use crate::{CsvError, CsvErrorKind};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Maximum number of ranges spelled out when displaying a group
+const MAX_DISPLAYED_RANGES: usize = 10;
+
+/// All errors of one kind and column, collapsed into ranges
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorGroup {
+    /// Name of the error kind, as returned by [`CsvErrorKind::name`]
+    pub kind: &'static str,
+    /// What sets this group apart from others of its kind, such as `in column 2 ("name")`
+    pub detail: Option<String>,
+    /// Total number of occurrences
+    pub count: usize,
+    /// Inclusive ranges of record (or line) numbers, in ascending order
+    pub ranges: Vec<(usize, usize)>,
+    /// The first few errors of this kind
+    pub examples: Vec<CsvError>,
+    line_based: bool,
+}
+
+/// Groups errors by kind and column, in order of first occurrence
+///
+/// `examples` is the number of errors kept verbatim for each group.
+pub fn group_errors(errors: &[CsvError], examples: usize) -> Vec<ErrorGroup> {
+    let mut groups: Vec<ErrorGroup> = Vec::new();
+    let mut numbers: Vec<Vec<usize>> = Vec::new();
+    let mut indices: HashMap<(&'static str, Option<String>), usize> = HashMap::new();
+
+    for error in errors {
+        let kind = error.error.name();
+        let detail = detail(&error.error);
+        let index = match indices.get(&(kind, detail.clone())) {
+            Some(&index) => index,
+            None => {
+                indices.insert((kind, detail.clone()), groups.len());
+                groups.push(ErrorGroup {
+                    kind,
+                    detail,
+                    count: 0,
+                    ranges: Vec::new(),
+                    examples: Vec::new(),
+                    line_based: error.error.is_line_based(),
+                });
+                numbers.push(Vec::new());
+                groups.len() - 1
+            }
+        };
+
+        let group = &mut groups[index];
+        group.count += 1;
+        if group.examples.len() < examples {
+            group.examples.push(error.clone());
+        }
+        numbers[index].push(error.record_num);
+    }
+
+    for (group, mut numbers) in groups.iter_mut().zip(numbers) {
+        numbers.sort_unstable();
+        numbers.dedup();
+        group.ranges = collapse_ranges(&numbers);
+    }
+
+    groups
+}
+
+/// What sets errors of one kind apart: their column, or the terminator
+/// found by the mixed line ending rule
+fn detail(kind: &CsvErrorKind) -> Option<String> {
+    match kind {
+        CsvErrorKind::MixedLineEnding { found, .. } => Some(format!("found {}", found)),
+        _ => kind.column().map(|column| format!("in {}", column)),
+    }
+}
+
+/// Errors that appeared or disappeared between two validations of a file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorDiff {
+    /// Errors found now but not before
+    pub added: Vec<CsvError>,
+    /// Errors found before but not now
+    pub fixed: Vec<CsvError>,
+}
+
+impl ErrorDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.fixed.is_empty()
+    }
+}
+
+/// Compares two validations of a file
+///
+/// Errors are matched by their record (or line) number and message, so
+/// inserting a record reports the errors after it as fixed and added again.
+pub fn diff_errors(previous: &[CsvError], current: &[CsvError]) -> ErrorDiff {
+    let unmatched = |errors: &[CsvError], others: &[CsvError]| {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for error in others {
+            *counts.entry(error.to_string()).or_default() += 1;
+        }
+        errors
+            .iter()
+            .filter(|error| match counts.get_mut(&error.to_string()) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            })
+            .cloned()
+            .collect()
+    };
+
+    ErrorDiff {
+        added: unmatched(current, previous),
+        fixed: unmatched(previous, current),
+    }
+}
+
+/// Collapses sorted, deduplicated numbers into inclusive ranges
+fn collapse_ranges(numbers: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &n in numbers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == n => *end = n,
+            _ => ranges.push((n, n)),
+        }
+    }
+    ranges
+}
+
+/// Formats a number with thousands separators, e.g. `4,000,000`
+pub fn format_count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(c);
+    }
+    formatted
+}
+
+impl fmt::Display for ErrorGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = if self.line_based { "line" } else { "record" };
+        let plural = if self.ranges.len() == 1 && self.ranges[0].0 == self.ranges[0].1 {
+            ""
+        } else {
+            "s"
+        };
+        write!(f, "{}", self.kind)?;
+        if let Some(detail) = &self.detail {
+            write!(f, " {}", detail)?;
+        }
+        write!(f, " on {}{} ", unit, plural)?;
+
+        for (i, (start, end)) in self.ranges.iter().take(MAX_DISPLAYED_RANGES).enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            if start == end {
+                write!(f, "{}", format_count(*start))?;
+            } else {
+                write!(f, "{}–{}", format_count(*start), format_count(*end))?;
+            }
+        }
+        if self.ranges.len() > MAX_DISPLAYED_RANGES {
+            write!(
+                f,
+                ", … ({} more ranges)",
+                format_count(self.ranges.len() - MAX_DISPLAYED_RANGES)
+            )?;
+        }
+
+        let occurrences = if self.count == 1 {
+            "occurrence"
+        } else {
+            "occurrences"
+        };
+        write!(f, " ({} {})", format_count(self.count), occurrences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Column;
+    use crate::line_endings::LineEnding;
+
+    fn error(record_num: usize, kind: CsvErrorKind) -> CsvError {
+        CsvError {
+            record: None,
+            record_num,
+            error: kind,
+        }
+    }
+
+    #[test]
+    fn test_group_errors() {
+        let mut errors: Vec<CsvError> = (1..=4_000)
+            .map(|n| error(n, CsvErrorKind::InvalidLineEnding))
+            .collect();
+        errors.push(error(2, CsvErrorKind::FieldCount));
+        errors.push(error(5, CsvErrorKind::FieldCount));
+        errors.push(error(6, CsvErrorKind::FieldCount));
+
+        let groups = group_errors(&errors, 2);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[0].to_string(),
+            "InvalidLineEnding on lines 1–4,000 (4,000 occurrences)"
+        );
+        assert_eq!(groups[0].examples.len(), 2);
+        assert_eq!(
+            groups[1].to_string(),
+            "FieldCount on records 2, 5–6 (3 occurrences)"
+        );
+        assert_eq!(groups[1].ranges, vec![(2, 2), (5, 6)]);
+    }
+
+    #[test]
+    fn test_groups_by_column_and_terminator() {
+        let column = |index: usize| Column {
+            index,
+            name: Some(format!("c{}", index)),
+        };
+        let mixed = |found| CsvErrorKind::MixedLineEnding {
+            expected: LineEnding::CrLf,
+            found,
+        };
+        let errors = vec![
+            error(1, CsvErrorKind::Tab(column(1))),
+            error(2, mixed(LineEnding::Lf)),
+            error(3, CsvErrorKind::Tab(column(2))),
+            error(4, mixed(LineEnding::Cr)),
+            error(5, CsvErrorKind::Tab(column(1))),
+        ];
+        let groups: Vec<String> = group_errors(&errors, 1)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                "Tab in column 1 (\"c1\") on records 1, 5 (2 occurrences)",
+                "MixedLineEnding found LF on line 2 (1 occurrence)",
+                "Tab in column 2 (\"c2\") on record 3 (1 occurrence)",
+                "MixedLineEnding found CR on line 4 (1 occurrence)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_many_ranges_are_elided() {
+        let errors: Vec<CsvError> = (0..30)
+            .map(|n| error(n * 2, CsvErrorKind::FieldCount))
+            .collect();
+        let groups = group_errors(&errors, 0);
+        assert!(
+            groups[0]
+                .to_string()
+                .ends_with(", 18, … (20 more ranges) (30 occurrences)")
+        );
+    }
+
+    #[test]
+    fn test_diff_errors() {
+        let previous = vec![
+            error(1, CsvErrorKind::InvalidLineEnding),
+            error(2, CsvErrorKind::FieldCount),
+            error(2, CsvErrorKind::FieldCount),
+        ];
+        let current = vec![
+            error(2, CsvErrorKind::FieldCount),
+            error(3, CsvErrorKind::FieldCount),
+        ];
+        let diff = diff_errors(&previous, &current);
+        assert_eq!(diff.added, vec![error(3, CsvErrorKind::FieldCount)]);
+        assert_eq!(
+            diff.fixed,
+            vec![
+                error(1, CsvErrorKind::InvalidLineEnding),
+                error(2, CsvErrorKind::FieldCount)
+            ]
+        );
+        assert!(diff_errors(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1000), "1,000");
+        assert_eq!(format_count(4_000_000), "4,000,000");
+    }
+}
@@ -0,0 +1,419 @@
+//! Schema files declaring relationships between CSV files
+//!
+//! A schema is a TOML file listing foreign keys: columns of one file whose
+//! values must exist in columns of another.
+//!
+//! ```toml
+//! [[foreign_keys]]
+//! file = "orders.csv"
+//! columns = ["customer_id"]
+//! references = { file = "customers.csv", columns = ["id"] }
+//! ```
+//!
+//! Paths are relative to the directory of the schema file. Rows whose key
+//! columns are all empty are treated as having no reference.
+//!
+//! Referenced files are read with the delimiter and comment character of the
+//! linted file, unless the reference declares its own:
+//!
+//! ```toml
+//! references = { file = "customers.txt", columns = ["id"], delimiter = "\t", comment = "#" }
+//! ```
+//!
+//! The preamble and footer settings describe the linted file only, and are
+//! never applied to referenced files.
+
+use crate::collector::ErrorCollector;
+use crate::decompress::decompress;
+use crate::tokenizer::{Delimiter, Reader, Utf8Error};
+use crate::unique::{column_indices, hash_fields};
+use crate::{CsvError, CsvErrorKind, ValidationOptions, csv_reader_builder};
+use csv::StringRecord;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Errors loading a schema or the files it references
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("cannot read '{0}': {1}")]
+    Io(PathBuf, io::Error),
+    #[error("invalid schema: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("'{0}': {1}")]
+    Csv(PathBuf, csv::Error),
+    #[error("'{0}': {1}")]
+    Utf8(PathBuf, Utf8Error),
+    #[error("'{0}': column {1:?} not found in header")]
+    UnknownColumn(PathBuf, String),
+    #[error("foreign key of '{0}' has {1} column(s) but references {2}")]
+    ColumnCount(PathBuf, usize, usize),
+    #[error("reference to '{0}': {1}")]
+    Dialect(PathBuf, String),
+}
+
+/// A parsed schema file
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Schema {
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+/// Columns of `file` whose values must exist in the referenced columns
+#[derive(Debug, Clone, Deserialize)]
+pub struct ForeignKey {
+    pub file: PathBuf,
+    pub columns: Vec<String>,
+    pub references: Reference,
+}
+
+/// The referenced file and columns of a foreign key
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reference {
+    pub file: PathBuf,
+    pub columns: Vec<String>,
+    /// Delimiter of the referenced file, when it differs from the linted file's
+    pub delimiter: Option<String>,
+    /// Comment character of the referenced file, or an empty string for none,
+    /// when it differs from the linted file's
+    pub comment: Option<String>,
+}
+
+impl Reference {
+    /// Options for reading the referenced file, given those of the linted file
+    fn options(&self, options: &ValidationOptions) -> Result<ValidationOptions, SchemaError> {
+        let invalid = |message: &str| SchemaError::Dialect(self.file.clone(), message.to_string());
+        // Only the dialect carries over; skipped lines describe the linted file
+        let mut dialect = ValidationOptions {
+            custom_delimiter: options.custom_delimiter.clone(),
+            comment: options.comment,
+            ..ValidationOptions::new(options.delimiter, options.lazy_quotes, options.rfc4180_mode)
+        };
+        if let Some(delimiter) = &self.delimiter {
+            if delimiter.is_empty() || delimiter.contains(['"', '\r', '\n']) {
+                return Err(invalid(
+                    "the delimiter can't be empty or contain quotes or line breaks",
+                ));
+            }
+            (dialect.delimiter, dialect.custom_delimiter) = match delimiter.as_bytes() {
+                &[byte] => (byte, None),
+                bytes => (b',', Some(Delimiter::Literal(bytes.to_vec()))),
+            };
+        }
+        if let Some(comment) = &self.comment {
+            dialect.comment = match comment.as_bytes() {
+                [] => None,
+                &[byte] => Some(byte),
+                _ => return Err(invalid("the comment must be a single ASCII character")),
+            };
+        }
+        let first_delimiter_byte = match &dialect.custom_delimiter {
+            Some(Delimiter::Literal(bytes)) => bytes.first().copied(),
+            Some(Delimiter::Pattern(_)) => None,
+            None => Some(dialect.delimiter),
+        };
+        if dialect.comment.is_some() && dialect.comment == first_delimiter_byte {
+            return Err(invalid("the comment character can't be the delimiter"));
+        }
+        Ok(dialect)
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.file.display(), self.columns.join(", "))
+    }
+}
+
+/// A foreign key to check while validating the referencing file
+#[derive(Debug, Clone)]
+pub struct ForeignKeyCheck {
+    /// Key columns in the referencing file
+    pub columns: Vec<String>,
+    /// The referenced file and columns, for reporting
+    pub target: String,
+    /// Hashes of the referenced keys
+    pub(crate) keys: Arc<HashSet<u128>>,
+}
+
+impl Schema {
+    /// Loads a schema file, resolving its paths against the file's directory
+    pub fn load(path: &Path) -> Result<Schema, SchemaError> {
+        let text = fs::read_to_string(path).map_err(|e| SchemaError::Io(path.to_path_buf(), e))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Schema::parse(&text, base)
+    }
+
+    /// Parses a schema, resolving its paths against `base`
+    pub fn parse(text: &str, base: &Path) -> Result<Schema, SchemaError> {
+        let mut schema: Schema = toml::from_str(text)?;
+        for foreign_key in &mut schema.foreign_keys {
+            foreign_key.file = base.join(&foreign_key.file);
+            foreign_key.references.file = base.join(&foreign_key.references.file);
+        }
+        Ok(schema)
+    }
+
+    /// Builds the checks for the foreign keys of `file`, loading the referenced keys
+    ///
+    /// Referenced files are read with the dialect in `options`, unless the
+    /// reference declares its own, and without its skipped lines.
+    pub fn foreign_key_checks(
+        &self,
+        file: &Path,
+        options: &ValidationOptions,
+    ) -> Result<Vec<ForeignKeyCheck>, SchemaError> {
+        self.foreign_keys
+            .iter()
+            .filter(|foreign_key| same_file(&foreign_key.file, file))
+            .map(|foreign_key| {
+                let reference = &foreign_key.references;
+                if foreign_key.columns.len() != reference.columns.len() {
+                    return Err(SchemaError::ColumnCount(
+                        foreign_key.file.clone(),
+                        foreign_key.columns.len(),
+                        reference.columns.len(),
+                    ));
+                }
+                let reference_options = reference.options(options)?;
+                Ok(ForeignKeyCheck {
+                    columns: foreign_key.columns.clone(),
+                    target: reference.to_string(),
+                    keys: Arc::new(load_keys(
+                        &reference.file,
+                        &reference.columns,
+                        &reference_options,
+                    )?),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Checks records against the foreign keys in the options
+pub(crate) struct ForeignKeyChecker<'a> {
+    keys: Vec<(Vec<usize>, &'a ForeignKeyCheck)>,
+}
+
+impl<'a> ForeignKeyChecker<'a> {
+    /// Resolves key columns against `header`; keys with unknown columns are skipped
+    pub fn new(options: &'a ValidationOptions, header: Option<&StringRecord>) -> Self {
+        let keys = options
+            .foreign_keys
+            .iter()
+            .filter_map(|check| Some((column_indices(&check.columns, header)?, check)))
+            .collect();
+        ForeignKeyChecker { keys }
+    }
+
+    pub fn check(&self, record: &StringRecord, record_num: usize, errors: &mut ErrorCollector) {
+        for (columns, check) in &self.keys {
+            // Records too short for the key are already reported as field count errors
+            let Some(values) = columns
+                .iter()
+                .map(|&i| record.get(i))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            if values.iter().all(|value| value.is_empty()) {
+                continue;
+            }
+            if !check.keys.contains(&hash_fields(values.iter().copied())) {
+                errors.push(CsvError {
+                    record: Some(record.iter().map(|s| s.to_string()).collect()),
+                    record_num,
+                    error: CsvErrorKind::MissingReference {
+                        key: format!("{:?}", values),
+                        target: check.target.clone(),
+                    },
+                });
+            }
+        }
+    }
+}
+
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Hashes of the values of `columns` in each record of the file at `path`
+fn load_keys(
+    path: &Path,
+    columns: &[String],
+    options: &ValidationOptions,
+) -> Result<HashSet<u128>, SchemaError> {
+    let io_error = |e| SchemaError::Io(path.to_path_buf(), e);
+    let mut content = Vec::new();
+    let file = File::open(path).map_err(io_error)?;
+    let (_, mut reader) = decompress(file).map_err(io_error)?;
+
+    let mut keys = HashSet::new();
+    let key = |record: &StringRecord, indices: &[usize]| {
+        hash_fields(indices.iter().map(|&i| record.get(i).unwrap_or("")))
+    };
+    if let Some(delimiter) = &options.custom_delimiter {
+        // The tokenizer works on the whole content
+        reader.read_to_end(&mut content).map_err(io_error)?;
+        let utf8_error = |e| SchemaError::Utf8(path.to_path_buf(), e);
+        let mut reader = Reader::new(&content, delimiter, options);
+        let mut header = StringRecord::new();
+        reader.read_record(&mut header).map_err(utf8_error)?;
+        let indices = key_indices(path, &header, columns)?;
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record).map_err(utf8_error)? {
+            keys.insert(key(&record, &indices));
+        }
+        return Ok(keys);
+    }
+
+    let mut csv_reader = csv_reader_builder(options)
+        .has_headers(true)
+        .from_reader(reader);
+    let header = csv_reader
+        .headers()
+        .map_err(|e| SchemaError::Csv(path.to_path_buf(), e))?;
+    let indices = key_indices(path, header, columns)?;
+    for record in csv_reader.records() {
+        let record = record.map_err(|e| SchemaError::Csv(path.to_path_buf(), e))?;
+        keys.insert(key(&record, &indices));
+    }
+    Ok(keys)
+}
+
+/// Positions of `columns` in the header of the file at `path`
+fn key_indices(
+    path: &Path,
+    header: &StringRecord,
+    columns: &[String],
+) -> Result<Vec<usize>, SchemaError> {
+    columns
+        .iter()
+        .map(|name| {
+            header
+                .iter()
+                .position(|column| column == name)
+                .ok_or_else(|| SchemaError::UnknownColumn(path.to_path_buf(), name.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CsvErrorKind, validate_slice_with_options};
+
+    #[test]
+    fn test_foreign_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("customers.csv"), "id,name\n1,Ann\n2,Bob\n").unwrap();
+        let orders = "order,customer_id\n10,1\n11,3\n12,\n13,2\n";
+        fs::write(dir.path().join("orders.csv"), orders).unwrap();
+
+        let schema = Schema::parse(
+            r#"
+            [[foreign_keys]]
+            file = "orders.csv"
+            columns = ["customer_id"]
+            references = { file = "customers.csv", columns = ["id"] }
+            "#,
+            dir.path(),
+        )
+        .unwrap();
+
+        let mut options = ValidationOptions::default();
+        assert!(
+            schema
+                .foreign_key_checks(&dir.path().join("customers.csv"), &options)
+                .unwrap()
+                .is_empty()
+        );
+        options.foreign_keys = schema
+            .foreign_key_checks(&dir.path().join("orders.csv"), &options)
+            .unwrap();
+
+        let result = validate_slice_with_options(orders.as_bytes(), &options).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].record_num, 2);
+        assert!(matches!(
+            &result.errors[0].error,
+            CsvErrorKind::MissingReference { key, .. } if key == "[\"3\"]"
+        ));
+    }
+
+    #[test]
+    fn test_reference_dialect() {
+        let dir = tempfile::tempdir().unwrap();
+        let customers = "id;name\n# closed accounts follow\n1;Ann\n2;Bob\n";
+        fs::write(dir.path().join("customers.csv"), customers).unwrap();
+        let orders = "Orders export\norder,customer_id\n10,1\n11,3\n13,2\nTotal: 3\n";
+        fs::write(dir.path().join("orders.csv"), orders).unwrap();
+
+        let schema = |reference: &str| {
+            Schema::parse(
+                &format!(
+                    r#"
+                    [[foreign_keys]]
+                    file = "orders.csv"
+                    columns = ["customer_id"]
+                    references = {{ file = "customers.csv", columns = ["id"]{} }}
+                    "#,
+                    reference
+                ),
+                dir.path(),
+            )
+            .unwrap()
+        };
+        let mut options = ValidationOptions::default();
+        options.skip.preamble = 1;
+        options.skip.footer = 1;
+
+        // The referenced file has no preamble or footer to skip
+        options.foreign_keys = schema(r##", delimiter = ";", comment = "#""##)
+            .foreign_key_checks(&dir.path().join("orders.csv"), &options)
+            .unwrap();
+        let result = validate_slice_with_options(orders.as_bytes(), &options).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert!(matches!(
+            &result.errors[0].error,
+            CsvErrorKind::MissingReference { key, .. } if key == "[\"3\"]"
+        ));
+
+        // Read with the linted file's comma, the header has no `id` column
+        assert!(matches!(
+            schema("").foreign_key_checks(&dir.path().join("orders.csv"), &options),
+            Err(SchemaError::UnknownColumn(_, column)) if column == "id"
+        ));
+        assert!(matches!(
+            schema(r#", delimiter = ";", comment = ";""#)
+                .foreign_key_checks(&dir.path().join("orders.csv"), &options),
+            Err(SchemaError::Dialect(..))
+        ));
+    }
+
+    #[test]
+    fn test_column_count_mismatch() {
+        let schema = Schema::parse(
+            r#"
+            [[foreign_keys]]
+            file = "a.csv"
+            columns = ["x", "y"]
+            references = { file = "b.csv", columns = ["x"] }
+            "#,
+            Path::new(""),
+        )
+        .unwrap();
+        assert!(matches!(
+            schema.foreign_key_checks(Path::new("a.csv"), &ValidationOptions::default()),
+            Err(SchemaError::ColumnCount(_, 2, 1))
+        ));
+    }
+}
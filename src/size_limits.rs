@@ -0,0 +1,343 @@
+//! Limits on field length, row width and row count
+//!
+//! Downstream systems often cap column widths (`VARCHAR(255)`) and row
+//! sizes. Records longer than [`SizeLimits::max_record_bytes`] are found by a
+//! structural scan before parsing and skipped without being parsed, so that
+//! a pathological input such as a multi-gigabyte unterminated quoted field
+//! is reported instead of being parsed as a single record.
+//!
+//! The scan runs over content that is already in memory. Memory-mapped files
+//! are scanned in place, but streamed inputs, such as stdin, pipes and
+//! decompressed files, are read whole first (see
+//! [`crate::validate_with_options`]), so the limit doesn't bound their
+//! memory use.
+
+use crate::collector::ErrorCollector;
+use crate::{
+    Column, CsvError, CsvErrorKind, RecordsOutcome, ValidationOptions, csv_reader_builder,
+    tokenizer, validate_records,
+};
+use csv::StringRecord;
+use std::fmt;
+use std::io::{Cursor, SeekFrom};
+use std::ops::Range;
+
+/// The unit a field length is measured in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthUnit {
+    Bytes,
+    /// Unicode scalar values
+    Chars,
+}
+
+impl fmt::Display for LengthUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LengthUnit::Bytes => write!(f, "bytes"),
+            LengthUnit::Chars => write!(f, "characters"),
+        }
+    }
+}
+
+/// A maximum field length, for every column or for one
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldLengthLimit {
+    /// The header name of the column, or `None` for every column
+    pub column: Option<String>,
+    pub max: usize,
+    pub unit: LengthUnit,
+}
+
+/// Limits on the size of fields, records and the file
+///
+/// A limit for a column replaces the limit for every column in the same unit.
+#[derive(Debug, Clone, Default)]
+pub struct SizeLimits {
+    pub field_lengths: Vec<FieldLengthLimit>,
+    /// Maximum number of fields per record
+    pub max_fields: Option<usize>,
+    /// Maximum size of a record in bytes, excluding its line break
+    pub max_record_bytes: Option<usize>,
+    /// Maximum number of data records
+    pub max_records: Option<usize>,
+}
+
+impl SizeLimits {
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.field_lengths.is_empty()
+            || self.max_fields.is_some()
+            || self.max_record_bytes.is_some()
+            || self.max_records.is_some()
+    }
+
+    /// Names of the columns with a limit of their own
+    pub(crate) fn columns(&self) -> impl Iterator<Item = &String> {
+        self.field_lengths
+            .iter()
+            .filter_map(|limit| limit.column.as_ref())
+    }
+}
+
+/// Checks the fields of each record against the field length and field count limits
+pub(crate) struct FieldLimits {
+    /// Byte and character limits for every column
+    default: [Option<usize>; 2],
+    /// Byte and character limits by column index, where they differ from the default
+    columns: Vec<[Option<usize>; 2]>,
+    max_fields: Option<usize>,
+}
+
+impl FieldLimits {
+    /// Resolves column limits against `header`; limits for unknown columns are ignored
+    pub fn new(options: &ValidationOptions, header: Option<&StringRecord>) -> Option<Self> {
+        let limits = &options.size_limits;
+        if limits.field_lengths.is_empty() && limits.max_fields.is_none() {
+            return None;
+        }
+
+        let slot = |unit| match unit {
+            LengthUnit::Bytes => 0,
+            LengthUnit::Chars => 1,
+        };
+        let mut default = [None; 2];
+        for limit in limits.field_lengths.iter().filter(|l| l.column.is_none()) {
+            default[slot(limit.unit)] = Some(limit.max);
+        }
+
+        let mut columns = vec![default; header.map_or(0, |h| h.len())];
+        for limit in &limits.field_lengths {
+            if let Some(name) = &limit.column
+                && let Some(i) = header.and_then(|h| h.iter().position(|column| column == name))
+            {
+                columns[i][slot(limit.unit)] = Some(limit.max);
+            }
+        }
+
+        Some(FieldLimits {
+            default,
+            columns,
+            max_fields: limits.max_fields,
+        })
+    }
+
+    pub fn check(
+        &self,
+        record: &StringRecord,
+        header: Option<&StringRecord>,
+        record_num: usize,
+        errors: &mut ErrorCollector,
+    ) {
+        let to_vec = || record.iter().map(|s| s.to_string()).collect();
+
+        if let Some(max) = self.max_fields
+            && record.len() > max
+        {
+            errors.push(CsvError {
+                record: Some(to_vec()),
+                record_num,
+                error: CsvErrorKind::TooManyFields {
+                    count: record.len(),
+                    max,
+                },
+            });
+        }
+
+        for (i, field) in record.iter().enumerate() {
+            let [max_bytes, max_chars] = self.columns.get(i).copied().unwrap_or(self.default);
+            let too_long = if let Some(max) = max_bytes
+                && field.len() > max
+            {
+                Some((field.len(), max, LengthUnit::Bytes))
+            } else if let Some(max) = max_chars
+                // A field can't have more characters than bytes
+                && field.len() > max
+                && field.chars().count() > max
+            {
+                Some((field.chars().count(), max, LengthUnit::Chars))
+            } else {
+                None
+            };
+
+            if let Some((length, max, unit)) = too_long {
+                errors.push(CsvError {
+                    record: Some(to_vec()),
+                    record_num,
+                    error: CsvErrorKind::FieldTooLong {
+                        column: Column::new(i, header),
+                        length,
+                        max,
+                        unit,
+                    },
+                });
+            }
+        }
+    }
+}
+
+/// A record over the byte limit, found before parsing
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OversizedRecord {
+    /// Number of the record, counting the header as record 0
+    pub index: usize,
+    /// Byte range of the record, excluding its line break
+    pub span: Range<usize>,
+}
+
+/// Finds the records longer than [`SizeLimits::max_record_bytes`]
+///
+/// Records are split the same way as by the csv crate, so that record
+/// numbers line up with the parsed records.
+pub(crate) fn oversized_records(
+    content: &[u8],
+    options: &ValidationOptions,
+) -> Vec<OversizedRecord> {
+    let Some(max) = options.size_limits.max_record_bytes else {
+        return Vec::new();
+    };
+
+    let mut oversized = Vec::new();
+    let mut index = 0;
+    tokenizer::records(content, options, |span| {
+        if span.len() > max {
+            oversized.push(OversizedRecord { index, span });
+        }
+        index += 1;
+    });
+    oversized
+}
+
+pub(crate) fn record_too_long(record: &OversizedRecord, options: &ValidationOptions) -> CsvError {
+    CsvError {
+        record: None,
+        record_num: record.index,
+        error: CsvErrorKind::RecordTooLong {
+            length: record.span.len(),
+            max: options.size_limits.max_record_bytes.unwrap_or_default(),
+        },
+    }
+}
+
+/// Validates the data records from `data_start` on, skipping the oversized records
+///
+/// Each stretch of records between oversized ones is parsed by its own
+/// reader, positioned so that parse errors report absolute positions.
+pub(crate) fn validate_records_skipping(
+    content: &[u8],
+    data_start: usize,
+    oversized: &[OversizedRecord],
+    header: Option<&StringRecord>,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) -> RecordsOutcome {
+    let mut start = data_start;
+    let mut records = 0;
+    for skipped in oversized.iter().map(Some).chain([None]) {
+        let end = skipped.map_or(content.len(), |record| record.span.start);
+
+        let mut position = csv::Position::new();
+        position
+            .set_byte(start as u64)
+            .set_line(1 + content[..start].iter().filter(|&&b| b == b'\n').count() as u64)
+            .set_record((records + 1) as u64);
+        let mut csv_reader = csv_reader_builder(options).from_reader(Cursor::new(&content[..end]));
+        if csv_reader
+            .seek_raw(SeekFrom::Start(start as u64), position)
+            .is_err()
+        {
+            break;
+        }
+        let outcome = validate_records(&mut csv_reader, content, header, records, options, errors);
+        records += outcome.records;
+        if outcome.stopped || errors.is_full() {
+            return RecordsOutcome { records, ..outcome };
+        }
+
+        let Some(record) = skipped else {
+            break;
+        };
+        records = record.index;
+        if options.sample.includes(record.index) {
+            errors.push(record_too_long(record, options));
+        }
+        start = record.span.end;
+    }
+
+    RecordsOutcome {
+        records,
+        stopped: false,
+        halted: false,
+    }
+}
+
+/// Reports an oversized header, which stops validation
+pub(crate) fn validate_header_size(
+    oversized: &[OversizedRecord],
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) -> bool {
+    match oversized.first() {
+        Some(record) if record.index == 0 => {
+            errors.push(record_too_long(record, options));
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Reports a file with more data records than [`SizeLimits::max_records`]
+///
+/// `records` is the number of records read, which undercounts when
+/// validation stopped early.
+pub(crate) fn validate_record_count(
+    records: usize,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) {
+    if let Some(max) = options.size_limits.max_records
+        && records > max
+    {
+        errors.push(CsvError {
+            record: None,
+            record_num: max + 1,
+            error: CsvErrorKind::TooManyRecords {
+                count: records,
+                max,
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oversized_records() {
+        let options = ValidationOptions {
+            size_limits: SizeLimits {
+                max_record_bytes: Some(5),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let content = b"a,b\r\n\r\nabcdef\n\"x\ny\",z\n12,34\n\"unterminated,,,";
+        let oversized = oversized_records(content, &options);
+        assert_eq!(
+            oversized,
+            vec![
+                OversizedRecord {
+                    index: 1,
+                    span: 7..13
+                },
+                OversizedRecord {
+                    index: 2,
+                    span: 14..21
+                },
+                OversizedRecord {
+                    index: 4,
+                    span: 28..44
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Skipping preamble and footer lines
+//!
+//! Exports from banks and spreadsheets often have title lines before the
+//! header and totals after the records. The lines skipped here are cut off
+//! before the content is parsed, so the first line kept is the header; line
+//! numbers in reports still count them. Lines end at CR, LF or CRLF, and
+//! skipped lines aren't checked by any rule.
+
+use crate::{ValidationOptions, line_endings};
+use regex::bytes::Regex;
+use std::ops::Range;
+
+/// Which lines are skipped at the start and end of a file
+#[derive(Debug, Clone, Default)]
+pub struct SkipLines {
+    /// Number of lines before the header
+    pub preamble: usize,
+    /// Number of lines at the end of the file
+    pub footer: usize,
+    /// Pattern of the first footer line; it and every line after it are skipped
+    ///
+    /// Only lines after the header are matched.
+    pub footer_pattern: Option<Regex>,
+}
+
+impl SkipLines {
+    /// Whether any line is skipped
+    pub fn is_enabled(&self) -> bool {
+        self.preamble > 0 || self.footer > 0 || self.footer_pattern.is_some()
+    }
+}
+
+/// The part of a file left after skipping lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Data {
+    /// Byte range of the lines kept, including the terminator of the last one
+    pub range: Range<usize>,
+    /// Number of the first line kept, counting from 1
+    pub first_line: usize,
+    /// Number and byte range, without the terminator, of each skipped line
+    pub skipped: Vec<(usize, Range<usize>)>,
+}
+
+/// Finds the lines of `content` kept by [`ValidationOptions::skip`]
+pub fn data(content: &[u8], options: &ValidationOptions) -> Data {
+    let skip = &options.skip;
+    if !skip.is_enabled() {
+        return Data {
+            range: 0..content.len(),
+            first_line: 1,
+            skipped: Vec::new(),
+        };
+    }
+
+    let lines = lines(content);
+    let preamble = skip.preamble.min(lines.len());
+    let mut footer = lines.len() - skip.footer.min(lines.len() - preamble);
+    if let Some(pattern) = &skip.footer_pattern
+        && let Some(i) =
+            (preamble + 1..footer).find(|&i| pattern.is_match(&content[lines[i].clone()]))
+    {
+        footer = i;
+    }
+
+    let start = lines.get(preamble).map_or(content.len(), |line| line.start);
+    let end = lines.get(footer).map_or(content.len(), |line| line.start);
+    let skipped = (0..preamble)
+        .chain(footer..lines.len())
+        .map(|i| (i + 1, lines[i].clone()))
+        .collect();
+    Data {
+        range: start..end,
+        first_line: preamble + 1,
+        skipped,
+    }
+}
+
+/// The data of `content` kept by [`ValidationOptions::skip`]
+pub fn data_slice<'a>(content: &'a [u8], options: &ValidationOptions) -> &'a [u8] {
+    if options.skip.is_enabled() {
+        &content[data(content, options).range]
+    } else {
+        content
+    }
+}
+
+/// The byte range of each line, without its terminator
+///
+/// A line break at the end of `content` doesn't start another line.
+pub(crate) fn lines(content: &[u8]) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    line_endings::for_each_line(content, |line, _| lines.push(line));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(preamble: usize, footer: usize, footer_pattern: Option<&str>) -> ValidationOptions {
+        ValidationOptions {
+            skip: SkipLines {
+                preamble,
+                footer,
+                footer_pattern: footer_pattern.map(|p| Regex::new(p).unwrap()),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_data() {
+        let content = b"Statement\r\n\r\ndate,amount\r\n2024-01-02,5\r\nTotal:,5\r\nEnd";
+        let data = data(content, &options(2, 0, Some("^Total:")));
+        assert_eq!(&content[data.range], b"date,amount\r\n2024-01-02,5\r\n");
+        assert_eq!(data.first_line, 3);
+        let skipped: Vec<(usize, &[u8])> = data
+            .skipped
+            .iter()
+            .map(|(line, range)| (*line, &content[range.clone()]))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                (1, &b"Statement"[..]),
+                (2, b""),
+                (5, b"Total:,5"),
+                (6, b"End")
+            ]
+        );
+
+        // The header is never matched as a footer
+        let content = b"Total:,x\nTotal:,1\n";
+        assert_eq!(
+            data_slice(content, &options(0, 0, Some("^Total:"))),
+            b"Total:,x\n"
+        );
+
+        assert_eq!(data_slice(content, &options(0, 1, None)), b"Total:,x\n");
+        assert_eq!(data_slice(content, &options(5, 1, None)), b"");
+        assert_eq!(data_slice(content, &options(0, 0, None)), content);
+    }
+}
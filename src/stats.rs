@@ -0,0 +1,726 @@
+//! Per-column statistics for profiling a file
+//!
+//! The file is read with the same dialect as validation, and the first record
+//! is taken as the header. Distinct counts and top values are exact by
+//! default; in approximate mode they are estimated in constant memory per
+//! column with HyperLogLog and the space-saving algorithm.
+
+use crate::csv_reader_builder;
+use crate::formula::is_number;
+use crate::options::ValidationOptions;
+use crate::skip;
+use crate::tokenizer::{self, Delimiter};
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Read;
+
+/// Values counted as nulls rather than as data
+const NULL_VALUES: [&str; 8] = ["NULL", "null", "Null", "NA", "N/A", "n/a", "\\N", "None"];
+
+/// HyperLogLog precision: 2^14 registers, for a standard error of about 0.8%
+const HLL_PRECISION: u32 = 14;
+
+/// Options controlling profiling
+#[derive(Debug, Clone)]
+pub struct StatsOptions {
+    /// Number of most frequent values reported per column
+    pub top_k: usize,
+    /// Whether to estimate distinct counts and top values in constant memory
+    pub approximate: bool,
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        StatsOptions {
+            top_k: 5,
+            approximate: false,
+        }
+    }
+}
+
+/// The type all non-null values of a column parse as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    /// The column has no non-null values
+    Empty,
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    String,
+}
+
+impl ValueType {
+    /// Whether `value`, which is not empty, parses as this type
+    pub(crate) fn matches(self, value: &str) -> bool {
+        match self {
+            ValueType::Empty => false,
+            ValueType::Integer => is_number(value) && !value.contains(['.', 'e', 'E']),
+            ValueType::Float => is_number(value),
+            ValueType::Boolean => {
+                value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+            }
+            ValueType::Date => is_date(value),
+            ValueType::String => true,
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ValueType::Empty => "empty",
+            ValueType::Integer => "integer",
+            ValueType::Float => "float",
+            ValueType::Boolean => "boolean",
+            ValueType::Date => "date",
+            ValueType::String => "string",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Statistics of one column
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnStats {
+    /// 1-based column index
+    pub index: usize,
+    /// Header name, if the header has this column
+    pub name: Option<String>,
+    /// Number of empty values
+    pub empty: usize,
+    /// Number of null values such as `NULL`, `NA` or `\N`
+    pub null: usize,
+    /// Number of distinct values, including empty and null ones
+    pub distinct: usize,
+    /// Whether `distinct` and `top_values` are estimates
+    pub approximate: bool,
+    /// Shortest and longest non-empty value, in characters
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub inferred_type: ValueType,
+    /// Share of non-null values that parse as numbers, from 0 to 1
+    pub numeric_share: f64,
+    /// Share of non-null values that parse as dates or date-times, from 0 to 1
+    pub date_share: f64,
+    /// Most frequent values with their counts, most frequent first
+    pub top_values: Vec<(String, usize)>,
+}
+
+/// Statistics of a whole file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Profile {
+    /// Number of data records
+    pub rows: usize,
+    /// Average size of a data record in bytes, including its line break
+    pub average_row_width: f64,
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Profiles the file read from `reader`
+///
+/// Stops with an error at the first record that cannot be parsed.
+pub fn profile<R: Read>(
+    mut reader: R,
+    options: &ValidationOptions,
+    stats_options: &StatsOptions,
+) -> Result<Profile, Box<dyn std::error::Error>> {
+    if options.skip.is_enabled() || options.custom_delimiter.is_some() {
+        // Footer lines are only known once the end of the file is, and the
+        // tokenizer works on the whole content
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        let content = skip::data_slice(&content, options);
+        return match &options.custom_delimiter {
+            Some(delimiter) => profile_tokenized(content, delimiter, options, stats_options),
+            None => profile_records(content, options, stats_options),
+        };
+    }
+    profile_records(reader, options, stats_options)
+}
+
+fn profile_records<R: Read>(
+    reader: R,
+    options: &ValidationOptions,
+    stats_options: &StatsOptions,
+) -> Result<Profile, Box<dyn std::error::Error>> {
+    // The csv crate reads a comment on the last line as an empty record unless
+    // it ends with a line break, so one is added; it is not counted below
+    let extra_bytes = u64::from(options.comment.is_some());
+    let reader = reader.chain(&b"\n"[..extra_bytes as usize]);
+    let mut csv_reader = csv_reader_builder(options).from_reader(reader);
+    let mut header = StringRecord::new();
+    if !csv_reader.read_record(&mut header)? {
+        return Ok(Profile::default());
+    }
+    let data_start = csv_reader.position().byte();
+
+    let mut profiler = RecordProfiler::new(stats_options);
+    let mut record = StringRecord::new();
+    while csv_reader.read_record(&mut record)? {
+        profiler.add(&record);
+    }
+    let data_bytes = (csv_reader.position().byte() - data_start).saturating_sub(extra_bytes);
+    Ok(profiler.finish(&header, data_bytes as usize))
+}
+
+/// Profiles `content`, split by the [`tokenizer`]
+fn profile_tokenized(
+    content: &[u8],
+    delimiter: &Delimiter,
+    options: &ValidationOptions,
+    stats_options: &StatsOptions,
+) -> Result<Profile, Box<dyn std::error::Error>> {
+    let mut reader = tokenizer::Reader::new(content, delimiter, options);
+    let mut header = StringRecord::new();
+    if !reader.read_record(&mut header)? {
+        return Ok(Profile::default());
+    }
+    let data_start = reader.position();
+
+    let mut profiler = RecordProfiler::new(stats_options);
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        profiler.add(&record);
+    }
+    Ok(profiler.finish(&header, reader.position() - data_start))
+}
+
+/// Running statistics of all columns
+struct RecordProfiler<'a> {
+    stats_options: &'a StatsOptions,
+    columns: Vec<ColumnProfiler>,
+    rows: usize,
+}
+
+impl<'a> RecordProfiler<'a> {
+    fn new(stats_options: &'a StatsOptions) -> Self {
+        RecordProfiler {
+            stats_options,
+            columns: Vec::new(),
+            rows: 0,
+        }
+    }
+
+    fn add(&mut self, record: &StringRecord) {
+        self.rows += 1;
+        // Records may have more fields than the header; those get unnamed columns
+        while self.columns.len() < record.len() {
+            self.columns.push(ColumnProfiler::new(self.stats_options));
+        }
+        for (column, value) in self.columns.iter_mut().zip(record.iter()) {
+            column.add(value);
+        }
+    }
+
+    /// The profile, given the header and the size of the data records in bytes
+    fn finish(mut self, header: &StringRecord, data_bytes: usize) -> Profile {
+        while self.columns.len() < header.len() {
+            self.columns.push(ColumnProfiler::new(self.stats_options));
+        }
+        let top_k = self.stats_options.top_k;
+        let columns = self
+            .columns
+            .into_iter()
+            .enumerate()
+            .map(|(i, column)| column.finish(i, header.get(i), top_k))
+            .collect();
+
+        Profile {
+            rows: self.rows,
+            average_row_width: if self.rows == 0 {
+                0.0
+            } else {
+                data_bytes as f64 / self.rows as f64
+            },
+            columns,
+        }
+    }
+}
+
+/// Running statistics of one column
+struct ColumnProfiler {
+    empty: usize,
+    null: usize,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    integers: usize,
+    floats: usize,
+    booleans: usize,
+    dates: usize,
+    values: Values,
+}
+
+impl ColumnProfiler {
+    fn new(stats_options: &StatsOptions) -> Self {
+        ColumnProfiler {
+            empty: 0,
+            null: 0,
+            min_length: None,
+            max_length: None,
+            integers: 0,
+            floats: 0,
+            booleans: 0,
+            dates: 0,
+            values: Values::new(stats_options),
+        }
+    }
+
+    fn add(&mut self, value: &str) {
+        self.values.add(value);
+        if value.is_empty() {
+            self.empty += 1;
+            return;
+        }
+
+        let length = value.chars().count();
+        self.min_length = Some(self.min_length.map_or(length, |min| min.min(length)));
+        self.max_length = Some(self.max_length.map_or(length, |max| max.max(length)));
+
+        if NULL_VALUES.contains(&value) {
+            self.null += 1;
+        } else if ValueType::Integer.matches(value) {
+            self.integers += 1;
+        } else if ValueType::Float.matches(value) {
+            self.floats += 1;
+        } else if ValueType::Boolean.matches(value) {
+            self.booleans += 1;
+        } else if ValueType::Date.matches(value) {
+            self.dates += 1;
+        }
+    }
+
+    fn finish(self, i: usize, name: Option<&str>, top_k: usize) -> ColumnStats {
+        let total = self.values.total();
+        let present = total - self.empty - self.null;
+        let share = |count: usize| {
+            if present == 0 {
+                0.0
+            } else {
+                count as f64 / present as f64
+            }
+        };
+
+        let numbers = self.integers + self.floats;
+        let inferred_type = if present == 0 {
+            ValueType::Empty
+        } else if self.integers == present {
+            ValueType::Integer
+        } else if numbers == present {
+            ValueType::Float
+        } else if self.booleans == present {
+            ValueType::Boolean
+        } else if self.dates == present {
+            ValueType::Date
+        } else {
+            ValueType::String
+        };
+
+        ColumnStats {
+            index: i + 1,
+            name: name.map(str::to_string),
+            empty: self.empty,
+            null: self.null,
+            distinct: self.values.distinct(),
+            approximate: matches!(self.values, Values::Approximate { .. }),
+            min_length: self.min_length,
+            max_length: self.max_length,
+            inferred_type,
+            numeric_share: share(numbers),
+            date_share: share(self.dates),
+            top_values: self.values.top(top_k),
+        }
+    }
+}
+
+/// The values seen in a column, for distinct counts and top values
+enum Values {
+    Exact(HashMap<String, usize>),
+    Approximate {
+        total: usize,
+        distinct: HyperLogLog,
+        top: SpaceSaving,
+    },
+}
+
+impl Values {
+    fn new(stats_options: &StatsOptions) -> Self {
+        if stats_options.approximate {
+            Values::Approximate {
+                total: 0,
+                distinct: HyperLogLog::new(HLL_PRECISION),
+                // Tracking more candidates than reported keeps the reported counts accurate
+                top: SpaceSaving::new((stats_options.top_k * 10).max(100)),
+            }
+        } else {
+            Values::Exact(HashMap::new())
+        }
+    }
+
+    fn add(&mut self, value: &str) {
+        match self {
+            Values::Exact(counts) => match counts.get_mut(value) {
+                Some(count) => *count += 1,
+                None => {
+                    counts.insert(value.to_string(), 1);
+                }
+            },
+            Values::Approximate {
+                total,
+                distinct,
+                top,
+            } => {
+                *total += 1;
+                distinct.insert(value);
+                top.insert(value);
+            }
+        }
+    }
+
+    fn total(&self) -> usize {
+        match self {
+            Values::Exact(counts) => counts.values().sum(),
+            Values::Approximate { total, .. } => *total,
+        }
+    }
+
+    fn distinct(&self) -> usize {
+        match self {
+            Values::Exact(counts) => counts.len(),
+            Values::Approximate { distinct, .. } => distinct.estimate(),
+        }
+    }
+
+    fn top(&self, k: usize) -> Vec<(String, usize)> {
+        let mut top: Vec<(String, usize)> = match self {
+            Values::Exact(counts) => counts
+                .iter()
+                .map(|(value, &count)| (value.clone(), count))
+                .collect(),
+            Values::Approximate { top, .. } => top.entries.clone(),
+        };
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(k);
+        top
+    }
+}
+
+/// HyperLogLog distinct count estimator
+struct HyperLogLog {
+    registers: Vec<u8>,
+    precision: u32,
+}
+
+impl HyperLogLog {
+    fn new(precision: u32) -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << precision],
+            precision,
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let index = (hash >> (64 - self.precision)) as usize;
+        // Position of the first set bit in the remaining bits, with a sentinel bit
+        // so an all-zero remainder yields the maximum rank
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&rank| 2f64.powi(-(rank as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+
+        // Linear counting is more accurate while many registers are still empty
+        let zeros = self.registers.iter().filter(|&&rank| rank == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+/// Space-saving heavy hitters: approximate counts of the most frequent values
+///
+/// Tracked values are kept ordered by count, so that finding the least
+/// frequent one to evict takes O(log capacity) rather than a scan.
+struct SpaceSaving {
+    /// Slot of each tracked value in `entries`
+    slots: HashMap<String, usize>,
+    /// Each tracked value and its count
+    entries: Vec<(String, usize)>,
+    /// Count and slot of each tracked value
+    by_count: BTreeSet<(usize, usize)>,
+    capacity: usize,
+}
+
+impl SpaceSaving {
+    fn new(capacity: usize) -> Self {
+        SpaceSaving {
+            slots: HashMap::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+            by_count: BTreeSet::new(),
+            capacity,
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        if let Some(&slot) = self.slots.get(value) {
+            let count = &mut self.entries[slot].1;
+            self.by_count.remove(&(*count, slot));
+            *count += 1;
+            self.by_count.insert((*count, slot));
+        } else if self.entries.len() < self.capacity {
+            let slot = self.entries.len();
+            self.slots.insert(value.to_string(), slot);
+            self.entries.push((value.to_string(), 1));
+            self.by_count.insert((1, slot));
+        } else {
+            // The new value replaces the least frequent one and inherits its count
+            let (min, slot) = self.by_count.pop_first().expect("capacity is non-zero");
+            self.slots.remove(&self.entries[slot].0);
+            self.slots.insert(value.to_string(), slot);
+            self.entries[slot] = (value.to_string(), min + 1);
+            self.by_count.insert((min + 1, slot));
+        }
+    }
+}
+
+/// Whether `value` is a date or date-time, such as `2024-01-31`, `31/01/2024` or
+/// `2024-01-31T12:00:00Z`
+fn is_date(value: &str) -> bool {
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    is_calendar_date(date) && time.is_none_or(is_time)
+}
+
+fn is_calendar_date(date: &str) -> bool {
+    let parts: Vec<&str> = date.split(['-', '/', '.']).collect();
+    let [a, b, c] = parts[..] else {
+        return false;
+    };
+    let number = |s: &str, len: std::ops::RangeInclusive<usize>| {
+        (len.contains(&s.len()) && s.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| s.parse::<u32>().ok())
+            .flatten()
+    };
+
+    if let (Some(year), Some(month), Some(day)) =
+        (number(a, 4..=4), number(b, 1..=2), number(c, 1..=2))
+    {
+        return is_valid_day(year, month, day);
+    }
+    // Day and month order can't be told apart, so either is accepted
+    if let (Some(x), Some(y), Some(year)) = (number(a, 1..=2), number(b, 1..=2), number(c, 4..=4)) {
+        return is_valid_day(year, y, x) || is_valid_day(year, x, y);
+    }
+    false
+}
+
+fn is_valid_day(year: u32, month: u32, day: u32) -> bool {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// Whether `time` is `HH:MM`, optionally with seconds, fractions and a UTC offset
+fn is_time(time: &str) -> bool {
+    let time = time
+        .strip_suffix(['Z', 'z'])
+        .or_else(|| {
+            let (time, offset) = time.rsplit_once(['+', '-'])?;
+            let offset = offset.replace(':', "");
+            (offset.len() == 4 && offset.bytes().all(|b| b.is_ascii_digit())).then_some(time)
+        })
+        .unwrap_or(time);
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+
+    let parts: Vec<&str> = time.split(':').collect();
+    let limits = [23, 59, 60]; // 60 allows for leap seconds
+    (2..=3).contains(&parts.len())
+        && parts
+            .iter()
+            .zip(limits)
+            .all(|(part, limit)| part.len() == 2 && part.parse::<u32>().is_ok_and(|n| n <= limit))
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rows: {}", self.rows)?;
+        writeln!(f, "average row width: {:.1} bytes", self.average_row_width)?;
+        for column in &self.columns {
+            writeln!(f)?;
+            match &column.name {
+                Some(name) => writeln!(
+                    f,
+                    "column {} ({:?}): {}",
+                    column.index, name, column.inferred_type
+                )?,
+                None => writeln!(f, "column {}: {}", column.index, column.inferred_type)?,
+            }
+            let about = if column.approximate { "~" } else { "" };
+            writeln!(
+                f,
+                "  empty: {}, null: {}, distinct: {}{}",
+                column.empty, column.null, about, column.distinct
+            )?;
+            if let (Some(min), Some(max)) = (column.min_length, column.max_length) {
+                writeln!(f, "  length: {}..{}", min, max)?;
+            }
+            writeln!(
+                f,
+                "  numbers: {:.1}%, dates: {:.1}%",
+                column.numeric_share * 100.0,
+                column.date_share * 100.0
+            )?;
+            let top: Vec<String> = column
+                .top_values
+                .iter()
+                .map(|(value, count)| format!("{:?} ({}{})", value, about, count))
+                .collect();
+            writeln!(f, "  top values: {}", top.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let csv_data = "id,price,date,name\n1,9.5,2024-01-31,a\n2,10,31/01/2024,b\n3,,NULL,a\n";
+        let profile = profile(
+            csv_data.as_bytes(),
+            &ValidationOptions::default(),
+            &StatsOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(profile.rows, 3);
+        assert_eq!(
+            profile.average_row_width,
+            (csv_data.len() - 19) as f64 / 3.0
+        );
+
+        let types: Vec<ValueType> = profile.columns.iter().map(|c| c.inferred_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                ValueType::Integer,
+                ValueType::Float,
+                ValueType::Date,
+                ValueType::String
+            ]
+        );
+
+        let price = &profile.columns[1];
+        assert_eq!((price.empty, price.null, price.distinct), (1, 0, 3));
+        assert_eq!((price.min_length, price.max_length), (Some(2), Some(3)));
+        assert_eq!(price.numeric_share, 1.0);
+
+        let date = &profile.columns[2];
+        assert_eq!((date.null, date.date_share), (1, 1.0));
+
+        let name = &profile.columns[3];
+        assert_eq!(name.name.as_deref(), Some("name"));
+        assert_eq!(name.top_values[0], ("a".to_string(), 2));
+    }
+
+    #[test]
+    fn test_profile_comments() {
+        let options = ValidationOptions {
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        let profile = profile(
+            &b"# units: m\nx\n1\n# 2\n3\n# end"[..],
+            &options,
+            &StatsOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(profile.rows, 2);
+        // The comments between and after the records count towards their width
+        assert_eq!(profile.average_row_width, 13.0 / 2.0);
+    }
+
+    #[test]
+    fn test_approximate_counts() {
+        let mut csv_data = String::from("n\n");
+        for n in 0..50_000 {
+            csv_data.push_str(&format!("{}\n", if n % 2 == 0 { 0 } else { n }));
+        }
+        let stats_options = StatsOptions {
+            top_k: 1,
+            approximate: true,
+        };
+        let profile = profile(
+            csv_data.as_bytes(),
+            &ValidationOptions::default(),
+            &stats_options,
+        )
+        .unwrap();
+        let column = &profile.columns[0];
+        assert!(column.approximate);
+        // 25,001 distinct values; HyperLogLog is within a few percent
+        assert!(
+            (24_000..26_000).contains(&column.distinct),
+            "{}",
+            column.distinct
+        );
+        assert_eq!(column.top_values[0].0, "0");
+    }
+
+    #[test]
+    fn test_space_saving() {
+        let mut top = SpaceSaving::new(2);
+        for value in ["a", "a", "a", "a", "b", "c", "c", "d"] {
+            top.insert(value);
+        }
+        // "c" evicted "b" with count 1, then "d" evicted "c" with count 3
+        let mut entries = top.entries.clone();
+        entries.sort();
+        assert_eq!(entries, vec![("a".to_string(), 4), ("d".to_string(), 4)]);
+        assert_eq!(top.slots.len(), 2);
+        assert_eq!(top.by_count.len(), 2);
+    }
+
+    #[test]
+    fn test_is_date() {
+        assert!(is_date("2024-02-29"));
+        assert!(is_date("12/31/2024"));
+        assert!(is_date("2024-01-31T12:30:00.123+02:00"));
+        assert!(is_date("2024-01-31 12:30"));
+        assert!(!is_date("2023-02-29"));
+        assert!(!is_date("2024-13-01"));
+        assert!(!is_date("2024-01-31T25:00"));
+        assert!(!is_date("1.5"));
+    }
+}
@@ -0,0 +1,518 @@
+//! Vectorized structural scanning of CSV data
+//!
+//! The input is classified 64 bytes at a time into bitmasks marking quotes,
+//! delimiters, CR and LF. Consumers then walk only the set bits instead of
+//! every byte. On x86_64 the classification uses AVX2 when the CPU supports it
+//! and SSE2 otherwise; other targets use a portable fallback.
+
+use std::ops::Range;
+
+/// Bitmasks of the structural characters in one 64-byte block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Block {
+    pub quotes: u64,
+    pub delimiters: u64,
+    pub cr: u64,
+    pub lf: u64,
+}
+
+impl Block {
+    fn structurals(&self) -> u64 {
+        self.quotes | self.delimiters | self.cr | self.lf
+    }
+
+    fn line_breaks(&self) -> u64 {
+        self.cr | self.lf
+    }
+}
+
+type Classifier = fn(&[u8; 64], u8) -> Block;
+
+/// Picks the fastest classifier supported by the running CPU
+fn classifier() -> Classifier {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            return |block, delimiter| unsafe { x86::classify_avx2(block, delimiter) };
+        }
+        |block, delimiter| unsafe { x86::classify_sse2(block, delimiter) }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        classify_portable
+    }
+}
+
+/// Classifies a block one byte at a time
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+fn classify_portable(block: &[u8; 64], delimiter: u8) -> Block {
+    let mut result = Block::default();
+    for (i, &byte) in block.iter().enumerate() {
+        let bit = 1u64 << i;
+        if byte == b'"' {
+            result.quotes |= bit;
+        }
+        if byte == delimiter {
+            result.delimiters |= bit;
+        }
+        if byte == b'\r' {
+            result.cr |= bit;
+        }
+        if byte == b'\n' {
+            result.lf |= bit;
+        }
+    }
+    result
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::Block;
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn classify_avx2(block: &[u8; 64], delimiter: u8) -> Block {
+        let lo = unsafe { _mm256_loadu_si256(block.as_ptr() as *const __m256i) };
+        let hi = unsafe { _mm256_loadu_si256(block.as_ptr().add(32) as *const __m256i) };
+
+        let mask = |needle: u8| {
+            let needle = _mm256_set1_epi8(needle as i8);
+            let lo = _mm256_movemask_epi8(_mm256_cmpeq_epi8(lo, needle)) as u32 as u64;
+            let hi = _mm256_movemask_epi8(_mm256_cmpeq_epi8(hi, needle)) as u32 as u64;
+            lo | (hi << 32)
+        };
+
+        Block {
+            quotes: mask(b'"'),
+            delimiters: mask(delimiter),
+            cr: mask(b'\r'),
+            lf: mask(b'\n'),
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn classify_sse2(block: &[u8; 64], delimiter: u8) -> Block {
+        let lanes: [__m128i; 4] = std::array::from_fn(|i| unsafe {
+            _mm_loadu_si128(block.as_ptr().add(i * 16) as *const __m128i)
+        });
+
+        let mask = |needle: u8| {
+            let needle = _mm_set1_epi8(needle as i8);
+            lanes.iter().enumerate().fold(0u64, |acc, (i, &lane)| {
+                let bits = _mm_movemask_epi8(_mm_cmpeq_epi8(lane, needle)) as u16 as u64;
+                acc | (bits << (i * 16))
+            })
+        };
+
+        Block {
+            quotes: mask(b'"'),
+            delimiters: mask(delimiter),
+            cr: mask(b'\r'),
+            lf: mask(b'\n'),
+        }
+    }
+}
+
+/// Calls `visit` with the offset and value of each byte selected by `select`, in order
+fn scan(
+    content: &[u8],
+    delimiter: u8,
+    select: impl Fn(&Block) -> u64,
+    mut visit: impl FnMut(usize, u8),
+) {
+    let classify = classifier();
+    let mut chunks = content.chunks_exact(64);
+
+    let mut base = 0;
+    for chunk in &mut chunks {
+        let block: &[u8; 64] = chunk.try_into().expect("chunks_exact yields 64 bytes");
+        let mut bits = select(&classify(block, delimiter));
+        while bits != 0 {
+            let offset = base + bits.trailing_zeros() as usize;
+            visit(offset, content[offset]);
+            bits &= bits - 1;
+        }
+        base += 64;
+    }
+
+    // The tail is padded into a full block; bits past the end are masked off
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
+        let mut block = [0u8; 64];
+        block[..remainder.len()].copy_from_slice(remainder);
+        let mut bits = select(&classify(&block, delimiter)) & ((1u64 << remainder.len()) - 1);
+        while bits != 0 {
+            let offset = base + bits.trailing_zeros() as usize;
+            visit(offset, content[offset]);
+            bits &= bits - 1;
+        }
+    }
+}
+
+/// Calls `visit` with the offset and value of every CR and LF in `content`
+pub fn scan_line_breaks(content: &[u8], visit: impl FnMut(usize, u8)) {
+    scan(content, b'\n', Block::line_breaks, visit);
+}
+
+/// Calls `visit` with the offset and value of every quote, delimiter, CR and LF in `content`
+pub fn scan_structurals(content: &[u8], delimiter: u8, visit: impl FnMut(usize, u8)) {
+    scan(content, delimiter, Block::structurals, visit);
+}
+
+/// Summary of the number of fields per record, header included
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FieldCounts {
+    /// Number of records, header included
+    pub records: usize,
+    /// Smallest number of fields in any record
+    pub min: usize,
+    /// Largest number of fields in any record
+    pub max: usize,
+}
+
+impl FieldCounts {
+    /// Whether every record has the same number of fields
+    pub fn is_uniform(&self) -> bool {
+        self.min == self.max
+    }
+
+    pub(crate) fn push(&mut self, fields: usize) {
+        if self.records == 0 {
+            self.min = fields;
+            self.max = fields;
+        } else {
+            self.min = self.min.min(fields);
+            self.max = self.max.max(fields);
+        }
+        self.records += 1;
+    }
+}
+
+/// Calls `visit` with the offset and value of every delimiter, CR and LF outside quoted fields
+///
+/// Quoted fields are recognized the same way as by the csv crate: a quote
+/// only opens a quoted field at the start of a field (when `quoting` is
+/// enabled), and a doubled quote inside it is an escape. So are comment
+/// lines, which start with `comment` at the start of a record and run to
+/// the next LF: only that LF is visited.
+pub fn scan_unquoted(
+    content: &[u8],
+    delimiter: u8,
+    quoting: bool,
+    comment: Option<u8>,
+    mut visit: impl FnMut(usize, u8),
+) {
+    let mut field_start = 0;
+    let mut in_quotes = false;
+    let mut in_comment = is_comment(content, 0, comment);
+    // Offset of a quote inside a quoted field that may be an escape or the closing quote
+    let mut pending_quote: Option<usize> = None;
+
+    scan_structurals(content, delimiter, |offset, byte| {
+        if in_comment {
+            if byte == b'\n' {
+                in_comment = is_comment(content, offset + 1, comment);
+                field_start = offset + 1;
+                visit(offset, byte);
+            }
+            return;
+        }
+
+        if let Some(quote) = pending_quote.take() {
+            if byte == b'"' && offset == quote + 1 {
+                // Doubled quote: an escaped quote, still inside the quoted field
+                return;
+            }
+            in_quotes = false;
+        }
+
+        if in_quotes {
+            if byte == b'"' {
+                pending_quote = Some(offset);
+            }
+            return;
+        }
+
+        if byte == delimiter {
+            field_start = offset + 1;
+            visit(offset, byte);
+        } else if byte == b'\r' || byte == b'\n' {
+            field_start = offset + 1;
+            in_comment = is_comment(content, offset + 1, comment);
+            visit(offset, byte);
+        } else if byte == b'"' && quoting && offset == field_start {
+            in_quotes = true;
+        }
+    });
+}
+
+/// Byte ranges of the fields of the first record in `raw`, quotes included
+///
+/// Empty lines and comment lines before the record are skipped; the ranges
+/// exclude the record terminator.
+pub fn field_spans(
+    raw: &[u8],
+    delimiter: u8,
+    quoting: bool,
+    comment: Option<u8>,
+) -> Vec<Range<usize>> {
+    let mut start = 0;
+    loop {
+        start += raw[start..]
+            .iter()
+            .position(|&b| b != b'\r' && b != b'\n')
+            .unwrap_or(raw.len() - start);
+        if !is_comment(raw, start, comment) {
+            break;
+        }
+        start += raw[start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(raw.len() - start);
+    }
+
+    let mut spans = Vec::new();
+    let mut field_start = start;
+    let mut record_end = None;
+    scan_unquoted(&raw[start..], delimiter, quoting, None, |offset, byte| {
+        if record_end.is_some() {
+            return;
+        }
+        if byte == delimiter {
+            spans.push(field_start..start + offset);
+            field_start = start + offset + 1;
+        } else {
+            record_end = Some(start + offset);
+        }
+    });
+    spans.push(field_start..record_end.unwrap_or(raw.len()));
+    spans
+}
+
+/// Calls `visit` with the byte range of every record, excluding its terminator
+///
+/// Records are split the same way as by the csv crate, so the n-th range is
+/// that of the n-th record it reads; empty lines and comment lines are skipped.
+pub fn scan_records(
+    content: &[u8],
+    delimiter: u8,
+    quoting: bool,
+    comment: Option<u8>,
+    mut visit: impl FnMut(Range<usize>),
+) {
+    let is_record =
+        |line: Range<usize>| !line.is_empty() && !is_comment(content, line.start, comment);
+    let mut line_start = 0;
+    scan_unquoted(content, delimiter, quoting, comment, |offset, byte| {
+        if byte == b'\r' || byte == b'\n' {
+            if is_record(line_start..offset) {
+                visit(line_start..offset);
+            }
+            line_start = offset + 1;
+        }
+    });
+    if is_record(line_start..content.len()) {
+        visit(line_start..content.len());
+    }
+}
+
+/// Calls `visit` with the byte range of every comment line, excluding its LF
+///
+/// Only lines starting where a record could start are comments, the same
+/// as for the csv crate.
+pub fn scan_comments(
+    content: &[u8],
+    delimiter: u8,
+    quoting: bool,
+    comment: u8,
+    mut visit: impl FnMut(Range<usize>),
+) {
+    let mut line_start = 0;
+    scan_unquoted(
+        content,
+        delimiter,
+        quoting,
+        Some(comment),
+        |offset, byte| {
+            if byte == b'\r' || byte == b'\n' {
+                if is_comment(content, line_start, Some(comment)) {
+                    visit(line_start..offset);
+                }
+                line_start = offset + 1;
+            }
+        },
+    );
+    if is_comment(content, line_start, Some(comment)) {
+        visit(line_start..content.len());
+    }
+}
+
+/// Whether a comment line starts at `start`, which must be the start of a record
+fn is_comment(content: &[u8], start: usize, comment: Option<u8>) -> bool {
+    comment.is_some() && content.get(start) == comment.as_ref()
+}
+
+/// Counts fields per record using the structural index
+///
+/// Records are split the same way as by the csv crate: CR, LF and CRLF all
+/// terminate a record, empty lines and comment lines are skipped, and a
+/// quote only opens a quoted field at the start of a field (when `quoting`
+/// is enabled).
+pub fn field_counts(
+    content: &[u8],
+    delimiter: u8,
+    quoting: bool,
+    comment: Option<u8>,
+) -> FieldCounts {
+    let mut counts = FieldCounts::default();
+    let mut record_start = 0;
+    let mut fields = 1;
+
+    scan_unquoted(content, delimiter, quoting, comment, |offset, byte| {
+        if byte == delimiter {
+            fields += 1;
+        } else {
+            if (offset > record_start || fields > 1) && !is_comment(content, record_start, comment)
+            {
+                counts.push(fields);
+            }
+            fields = 1;
+            record_start = offset + 1;
+        }
+    });
+
+    if (content.len() > record_start || fields > 1) && !is_comment(content, record_start, comment) {
+        counts.push(fields);
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv::{ReaderBuilder, StringRecord};
+
+    /// Field counts according to the csv crate, for comparison
+    fn csv_field_counts(
+        content: &[u8],
+        delimiter: u8,
+        quoting: bool,
+        comment: Option<u8>,
+    ) -> FieldCounts {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .quoting(quoting)
+            .comment(comment)
+            .from_reader(content);
+        let mut counts = FieldCounts::default();
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record).unwrap() {
+            counts.push(record.len());
+        }
+        counts
+    }
+
+    #[test]
+    fn test_classifiers_agree() {
+        let mut block = [0u8; 64];
+        for (i, byte) in block.iter_mut().enumerate() {
+            *byte = b"a,\"\r\n;x"[i % 7];
+        }
+        let expected = classify_portable(&block, b',');
+        assert_eq!(classifier()(&block, b','), expected);
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(unsafe { x86::classify_sse2(&block, b',') }, expected);
+    }
+
+    #[test]
+    fn test_scan_line_breaks() {
+        let content = format!("{}\r\n{}\n", "a".repeat(70), "b".repeat(3));
+        let mut found = Vec::new();
+        scan_line_breaks(content.as_bytes(), |offset, byte| {
+            found.push((offset, byte))
+        });
+        assert_eq!(found, vec![(70, b'\r'), (71, b'\n'), (75, b'\n')]);
+    }
+
+    #[test]
+    fn test_field_counts_match_csv_crate() {
+        let samples: &[&[u8]] = &[
+            b"a,b,c\r\n1,2,3\r\n",
+            b"a,b,c\n1,2\n\n\n4,5,6,7",
+            b"a,b\r\n\"x,y\",\"multi\r\nline\"\r\n",
+            b"a,b\n\"he said \"\"hi\"\"\",z\n",
+            b"a,b\nx\"y,z\n\"q\"tail,1\n",
+            b"a,b\r\r\n1,2\r3,4\r\n",
+            b"\"a\"\"\",b\n\"\"\"\",\"\"\n",
+            b"a,b\n\"unterminated,x\n1,2\n",
+            b"a,\n,\n,,\n",
+            b"# title, \"quoted\nstill\"\na,b\n#,\"x\n1,2\r\n#\r\n3,4\r#c,d\n5#,6\n#\n",
+            b"a,b\n\"#x\n#y\",z\n  #,1\n",
+        ];
+
+        for sample in samples {
+            for quoting in [true, false] {
+                for comment in [None, Some(b'#')] {
+                    assert_eq!(
+                        field_counts(sample, b',', quoting, comment),
+                        csv_field_counts(sample, b',', quoting, comment),
+                        "sample {:?}, quoting {}, comment {:?}",
+                        String::from_utf8_lossy(sample),
+                        quoting,
+                        comment
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_comments() {
+        let content = b"#a,\"b\na,b\r\n#c\r\n\"x\n#y\",1\n#";
+        let mut records = Vec::new();
+        scan_records(content, b',', true, Some(b'#'), |span| {
+            records.push(&content[span])
+        });
+        assert_eq!(records, vec![&b"a,b"[..], b"\"x\n#y\",1"]);
+
+        let mut comments = Vec::new();
+        scan_comments(content, b',', true, b'#', |span| {
+            comments.push(&content[span])
+        });
+        assert_eq!(comments, vec![&b"#a,\"b"[..], b"#c\r", b"#"]);
+    }
+
+    #[test]
+    fn test_field_spans() {
+        let raw = b"\r\n\"a,b\",c,\"d\"\"\"\r\nx";
+        let spans = field_spans(raw, b',', true, None);
+        assert_eq!(spans, vec![2..7, 8..9, 10..15]);
+        assert_eq!(&raw[spans[2].clone()], b"\"d\"\"\"");
+        assert_eq!(field_spans(b"a,", b',', true, None), vec![0..1, 2..2]);
+        assert_eq!(
+            field_spans(b"#x,\"y\r\n\n#\na,b", b',', true, Some(b'#')),
+            vec![10..11, 12..13]
+        );
+    }
+
+    #[test]
+    fn test_field_counts_across_blocks() {
+        let mut content = String::from("id,name,notes\r\n");
+        for i in 0..200 {
+            content.push_str(&format!(
+                "{},\"name, {}\",\"line\r\nbreak \"\"{}\"\"\"\r\n",
+                i, i, i
+            ));
+        }
+        let counts = field_counts(content.as_bytes(), b',', true, None);
+        assert_eq!(
+            counts,
+            csv_field_counts(content.as_bytes(), b',', true, None)
+        );
+        assert!(counts.is_uniform());
+        assert_eq!(counts.records, 201);
+    }
+}
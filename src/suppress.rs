@@ -0,0 +1,447 @@
+//! Suppressing errors with ignore files and directives
+//!
+//! Errors can be ignored for particular records in two kinds of sidecar
+//! file. A `.csvlintignore` in the directory of the CSV file lists one
+//! suppression per line, as the file name, comma-separated rule names (or
+//! `*` for every rule), and optionally record numbers or a key value:
+//!
+//! ```text
+//! # file       rules                  records or key
+//! orders.csv   FieldCount             10-20,35
+//! orders.csv   DuplicateKey,Tab       customer_id=42
+//! legacy.csv   *
+//! ```
+//!
+//! A `<file>.csvlint.toml` next to the CSV file, such as
+//! `orders.csv.csvlint.toml`, holds suppressions for that file only:
+//!
+//! ```toml
+//! [[ignore]]
+//! rules = ["FieldCount"]
+//! records = ["10-20", "35"]
+//!
+//! [[ignore]]
+//! rules = ["DuplicateKey"]
+//! column = "customer_id"
+//! values = ["42", "43"]
+//! ```
+//!
+//! When the dialect has comment lines, a comment such as
+//! `# csvlint-disable-next-line FieldCount` suppresses the listed rules, or
+//! every rule if none are listed, on the record that follows it: rules about
+//! lines, such as line endings, on the lines of that record.
+//!
+//! Record numbers count data records from 1; for rules about lines, such as
+//! line endings, they are line numbers.
+
+use crate::line_endings::count_line_breaks;
+use crate::schema::same_file;
+use crate::{CsvError, CsvErrorKind, ValidationOptions, skip, tokenizer};
+use csv::StringRecord;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Name of the ignore file read from the directory of each CSV file
+pub const IGNORE_FILE: &str = ".csvlintignore";
+
+/// Suffix of the per-file ignore file, appended to the CSV file's name
+pub const IGNORE_SUFFIX: &str = ".csvlint.toml";
+
+/// The directive that suppresses errors on the next record
+const DIRECTIVE: &str = "csvlint-disable-next-line";
+
+/// Errors reading ignore files
+#[derive(Debug, Error)]
+pub enum SuppressionError {
+    #[error("cannot read '{0}': {1}")]
+    Io(PathBuf, io::Error),
+    #[error("invalid '{0}': {1}")]
+    Toml(PathBuf, toml::de::Error),
+    #[error("'{0}' line {1}: {2}")]
+    Syntax(PathBuf, usize, String),
+}
+
+/// Errors to ignore: some rules, on some records
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Suppression {
+    /// Names of the rules, or `None` for every rule
+    pub rules: Option<Vec<String>>,
+    /// Record (or line) numbers, or empty for every record
+    pub records: Vec<RangeInclusive<usize>>,
+    /// Line numbers matched by rules about lines instead of `records`, if not empty
+    pub lines: Vec<RangeInclusive<usize>>,
+    /// A column and the values of it whose records are ignored
+    pub key: Option<(String, HashSet<String>)>,
+}
+
+/// All suppressions that apply to a file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Suppressions {
+    pub suppressions: Vec<Suppression>,
+}
+
+/// A per-file ignore file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IgnoreFile {
+    #[serde(default)]
+    ignore: Vec<IgnoreEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IgnoreEntry {
+    rules: Vec<String>,
+    #[serde(default)]
+    records: Vec<String>,
+    column: Option<String>,
+    #[serde(default)]
+    values: Vec<String>,
+}
+
+impl Suppressions {
+    /// Loads the suppressions for the CSV file at `path` from its ignore files
+    pub fn for_file(path: &Path) -> Result<Self, SuppressionError> {
+        let mut suppressions = Suppressions::default();
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let ignore_file = dir.join(IGNORE_FILE);
+        if let Some(text) = read_optional(&ignore_file)? {
+            for (i, line) in text.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let syntax =
+                    |message: String| SuppressionError::Syntax(ignore_file.clone(), i + 1, message);
+                let mut words = line.split_whitespace();
+                let (Some(file), Some(rules)) = (words.next(), words.next()) else {
+                    return Err(syntax("expected a file name and rules".to_string()));
+                };
+                let mut suppression = Suppression {
+                    rules: parse_rules(rules.split(',')).map_err(syntax)?,
+                    ..Default::default()
+                };
+                for selector in words {
+                    match selector.split_once('=') {
+                        Some((column, value)) => {
+                            suppression.key =
+                                Some((column.to_string(), HashSet::from([value.to_string()])));
+                        }
+                        None => suppression
+                            .records
+                            .extend(parse_records(selector.split(',')).map_err(syntax)?),
+                    }
+                }
+                if same_file(&dir.join(file), path) {
+                    suppressions.suppressions.push(suppression);
+                }
+            }
+        }
+
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(IGNORE_SUFFIX);
+        let sidecar = PathBuf::from(sidecar);
+        if let Some(text) = read_optional(&sidecar)? {
+            let file: IgnoreFile =
+                toml::from_str(&text).map_err(|e| SuppressionError::Toml(sidecar.clone(), e))?;
+            for (i, entry) in file.ignore.into_iter().enumerate() {
+                let invalid =
+                    |message: String| SuppressionError::Syntax(sidecar.clone(), i + 1, message);
+                let key = match entry.column {
+                    Some(column) => Some((column, entry.values.into_iter().collect())),
+                    None if !entry.values.is_empty() => {
+                        return Err(invalid("values without a column".to_string()));
+                    }
+                    None => None,
+                };
+                suppressions.suppressions.push(Suppression {
+                    rules: parse_rules(entry.rules.iter().map(String::as_str)).map_err(invalid)?,
+                    records: parse_records(entry.records.iter().map(String::as_str))
+                        .map_err(invalid)?,
+                    key,
+                    ..Default::default()
+                });
+            }
+        }
+
+        Ok(suppressions)
+    }
+
+    /// Adds the suppressions of `csvlint-disable-next-line` directives in
+    /// the comment lines of `content`, the content of the file at `path`, if
+    /// comments are enabled
+    pub fn add_directives(
+        &mut self,
+        path: &Path,
+        content: &[u8],
+        options: &ValidationOptions,
+    ) -> Result<(), SuppressionError> {
+        let Some(comment) = options.comment else {
+            return Ok(());
+        };
+        let data = skip::data(content, options);
+        let content = &content[data.range];
+        let line_of = |offset: usize| data.first_line + count_line_breaks(&content[..offset]);
+        let mut records = Vec::new();
+        tokenizer::records(content, options, |span| records.push(span));
+
+        let mut result = Ok(());
+        tokenizer::comments(content, options, comment, |span| {
+            if result.is_err() {
+                return;
+            }
+            let rules = match parse_directive(&content[span.start + 1..span.end]) {
+                Some(Ok(rules)) => rules,
+                Some(Err(message)) => {
+                    let line = line_of(span.start);
+                    result = Err(SuppressionError::Syntax(path.to_path_buf(), line, message));
+                    return;
+                }
+                None => return,
+            };
+            // The next record is the first one starting after the comment
+            let record = records.partition_point(|record| record.start < span.end);
+            let lines = records.get(record).map(|record| {
+                let first = line_of(record.start);
+                first..=first + count_line_breaks(&content[record.clone()])
+            });
+            self.suppressions.push(Suppression {
+                rules,
+                records: vec![record..=record],
+                lines: lines.into_iter().collect(),
+                key: None,
+            });
+        });
+        result
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.suppressions.is_empty()
+    }
+
+    /// Removes the suppressed errors of the file with `content`, returning how many there were
+    pub fn apply(
+        &self,
+        errors: &mut Vec<CsvError>,
+        content: &[u8],
+        options: &ValidationOptions,
+    ) -> usize {
+        // Key values are looked up by the column's position in the header
+        let header = if self.suppressions.iter().any(|s| s.key.is_some()) {
+            tokenizer::first_record(skip::data_slice(content, options), options)
+        } else {
+            None
+        };
+        let header = header.as_ref();
+
+        let before = errors.len();
+        errors.retain(|error| {
+            !self
+                .suppressions
+                .iter()
+                .any(|suppression| suppression.matches(error, header))
+        });
+        before - errors.len()
+    }
+}
+
+impl Suppression {
+    fn matches(&self, error: &CsvError, header: Option<&StringRecord>) -> bool {
+        let name = error.error.name();
+        let rule = self
+            .rules
+            .as_ref()
+            .is_none_or(|rules| rules.iter().any(|rule| rule == name));
+        let numbers = if error.error.is_line_based() && !self.lines.is_empty() {
+            &self.lines
+        } else {
+            &self.records
+        };
+        let record = numbers.is_empty()
+            || numbers
+                .iter()
+                .any(|numbers| numbers.contains(&error.record_num));
+        let key = self.key.as_ref().is_none_or(|(column, values)| {
+            let index = header.and_then(|header| header.iter().position(|name| name == column));
+            let value = index.and_then(|i| error.record.as_ref()?.get(i));
+            value.is_some_and(|value| values.contains(value))
+        });
+        rule && record && key
+    }
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, SuppressionError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(SuppressionError::Io(path.to_path_buf(), e)),
+    }
+}
+
+/// Parses rule names; `*` stands for every rule
+fn parse_rules<'a>(rules: impl Iterator<Item = &'a str>) -> Result<Option<Vec<String>>, String> {
+    let mut names = Vec::new();
+    for rule in rules {
+        if rule == "*" {
+            return Ok(None);
+        }
+        if !CsvErrorKind::NAMES.contains(&rule) {
+            return Err(format!("unknown rule '{}'", rule));
+        }
+        names.push(rule.to_string());
+    }
+    Ok(Some(names))
+}
+
+/// Parses record numbers ("35") and inclusive ranges of them ("10-20")
+fn parse_records<'a>(
+    records: impl Iterator<Item = &'a str>,
+) -> Result<Vec<RangeInclusive<usize>>, String> {
+    records
+        .map(|spec| {
+            let invalid = || format!("invalid records '{}', expected e.g. 35 or 10-20", spec);
+            let (start, end) = spec.split_once('-').unwrap_or((spec, spec));
+            let start = start.trim().parse().map_err(|_| invalid())?;
+            let end = end.trim().parse().map_err(|_| invalid())?;
+            Ok(start..=end)
+        })
+        .collect()
+}
+
+/// The rules a directive comment suppresses, `Some(Ok(None))` for every
+/// rule, or `None` if the comment isn't a directive
+fn parse_directive(comment: &[u8]) -> Option<Result<Option<Vec<String>>, String>> {
+    let comment = std::str::from_utf8(comment).ok()?.trim();
+    let rules = comment.strip_prefix(DIRECTIVE)?;
+    let mut rules = rules
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|rule| !rule.is_empty())
+        .peekable();
+    if rules.peek().is_none() {
+        return Some(Ok(None));
+    }
+    Some(parse_rules(rules))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate_slice_with_options;
+
+    #[test]
+    fn test_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.csv");
+        fs::write(&path, "id,name\n1\n2\n3, x\n4,y\n4,z\n").unwrap();
+        fs::write(
+            dir.path().join(IGNORE_FILE),
+            "# known problems\norders.csv FieldCount 1\nother.csv * \norders.csv * id=3\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("orders.csv.csvlint.toml"),
+            "[[ignore]]\nrules = [\"DuplicateKey\"]\nrecords = [\"4-10\"]\n",
+        )
+        .unwrap();
+
+        let suppressions = Suppressions::for_file(&path).unwrap();
+        assert_eq!(suppressions.suppressions.len(), 3);
+
+        let options = ValidationOptions {
+            surrounding_whitespace: crate::FieldScope::All,
+            unique_key: vec!["id".to_string()],
+            ..Default::default()
+        };
+        let content = fs::read(&path).unwrap();
+        let mut errors = validate_slice_with_options(&content, &options)
+            .unwrap()
+            .errors;
+        assert_eq!(suppressions.apply(&mut errors, &content, &options), 3);
+        let remaining: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            remaining,
+            vec!["Record #2 has error: wrong number of fields"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_ignore_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        fs::write(dir.path().join(IGNORE_FILE), "data.csv FieldCuont\n").unwrap();
+        let error = Suppressions::for_file(&path).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("line 1: unknown rule 'FieldCuont'")
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let options = ValidationOptions {
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        let content = b"# csvlint-disable-next-line\na,b\n1\n#  csvlint-disable-next-line FieldCount, Tab\n\n2\n3\n";
+        let mut suppressions = Suppressions::default();
+        suppressions
+            .add_directives(Path::new("data.csv"), content, &options)
+            .unwrap();
+        assert_eq!(
+            suppressions.suppressions,
+            vec![
+                Suppression {
+                    rules: None,
+                    records: vec![0..=0],
+                    lines: vec![2..=2],
+                    key: None,
+                },
+                Suppression {
+                    rules: Some(vec!["FieldCount".to_string(), "Tab".to_string()]),
+                    records: vec![2..=2],
+                    lines: vec![6..=6],
+                    key: None,
+                },
+            ]
+        );
+
+        let content = b"a,b\n# csvlint-disable-next-line FieldCuont\n1\n";
+        let error = Suppressions::default()
+            .add_directives(Path::new("data.csv"), content, &options)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "'data.csv' line 2: unknown rule 'FieldCuont'"
+        );
+    }
+
+    #[test]
+    fn test_directives_on_lines() {
+        let options = ValidationOptions {
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        let content =
+            b"a,b\r\n# csvlint-disable-next-line MixedLineEnding\r\n1,2\n3,4\r\n5,6\n7,8\r\n";
+        let mut errors = validate_slice_with_options(content, &options)
+            .unwrap()
+            .errors;
+        let mut suppressions = Suppressions::default();
+        suppressions
+            .add_directives(Path::new("data.csv"), content, &options)
+            .unwrap();
+        assert_eq!(suppressions.apply(&mut errors, content, &options), 1);
+        let remaining: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            remaining,
+            vec!["Line #5 has error: mixed line endings: found LF, file predominantly uses CRLF"]
+        );
+    }
+}
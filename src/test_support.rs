@@ -0,0 +1,18 @@
+//! Helpers shared by the tests of the library and of the binary, which
+//! includes this file as its own module
+
+use std::path::Path;
+use std::process::Command;
+
+/// Runs git in `dir` as a test user, failing the test when git fails
+pub fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success(), "git {:?} failed", args);
+}
@@ -0,0 +1,593 @@
+//! Tokenizer for delimiters the csv crate can't split on
+//!
+//! The csv crate only splits fields on a single byte, so feeds delimited by
+//! `||`, `~|~`, a non-ASCII character such as `¦`, or a regular expression
+//! are read here instead. Fields are otherwise split the same way as by the
+//! csv crate: CR, LF and CRLF terminate a record, empty lines and comment
+//! lines are skipped, a quote only opens a quoted field at the start of a
+//! field (when quoting is enabled), a doubled quote inside it is an escape,
+//! and anything after the closing quote is kept as it is. A delimiter never
+//! spans a line break.
+//!
+//! The scanning functions mirror those of [`structural`]; the functions
+//! taking [`ValidationOptions`] at the end of this module call whichever of
+//! the two reads the dialect of the options.
+
+use crate::structural::{self, FieldCounts};
+use crate::{ValidationOptions, csv_reader_builder, line_endings};
+use csv::{ByteRecord, StringRecord};
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::ops::Range;
+use thiserror::Error;
+
+/// A field delimiter the csv crate can't split on
+#[derive(Debug, Clone)]
+pub enum Delimiter {
+    /// A fixed sequence of bytes, such as `||` or the UTF-8 encoding of `¦`
+    Literal(Vec<u8>),
+    /// Any non-empty match of a regular expression, such as `\s*;\s*`
+    Pattern(Regex),
+}
+
+impl Delimiter {
+    /// A delimiter matching the regular expression `pattern`
+    pub fn pattern(pattern: &str) -> Result<Delimiter, regex::Error> {
+        // Anchored, so that only matches starting at the current byte count
+        Regex::new(&format!("^(?:{})", pattern)).map(Delimiter::Pattern)
+    }
+
+    /// Length of the delimiter at the start of `rest`, which ends at a line break
+    fn match_len(&self, rest: &[u8]) -> Option<usize> {
+        match self {
+            Delimiter::Literal(bytes) => rest.starts_with(bytes).then_some(bytes.len()),
+            Delimiter::Pattern(regex) => regex.find(rest).map(|m| m.end()).filter(|&len| len > 0),
+        }
+    }
+}
+
+/// A delimiter or line break outside quoted fields
+enum Token {
+    Delimiter(Range<usize>),
+    LineBreak(usize),
+}
+
+/// Calls `visit` with every delimiter and line break outside quoted fields
+///
+/// Comment lines start with `comment` at the start of a record and run to
+/// the next LF: only that LF is visited.
+fn scan(
+    content: &[u8],
+    delimiter: &Delimiter,
+    quoting: bool,
+    comment: Option<u8>,
+    mut visit: impl FnMut(Token),
+) {
+    let line_break = |from: usize| {
+        content[from..]
+            .iter()
+            .position(|&b| b == b'\r' || b == b'\n')
+            .map_or(content.len(), |i| from + i)
+    };
+    let mut i = 0;
+    // The first line break at or after the last position looked at
+    let mut line_end = 0;
+    let mut record_start = true;
+    let mut field_start = true;
+
+    while i < content.len() {
+        let byte = content[i];
+        if record_start && comment == Some(byte) {
+            let Some(lf) = content[i..].iter().position(|&b| b == b'\n') else {
+                return;
+            };
+            visit(Token::LineBreak(i + lf));
+            i += lf + 1;
+            continue;
+        }
+        if byte == b'\r' || byte == b'\n' {
+            visit(Token::LineBreak(i));
+            record_start = true;
+            field_start = true;
+            i += 1;
+            continue;
+        }
+        record_start = false;
+
+        if field_start && quoting && byte == b'"' {
+            // Skip to the closing quote; an unterminated quote runs to the end
+            let mut j = i + 1;
+            loop {
+                let Some(quote) = content[j..].iter().position(|&b| b == b'"') else {
+                    return;
+                };
+                j += quote + 1;
+                if content.get(j) != Some(&b'"') {
+                    break;
+                }
+                j += 1;
+            }
+            i = j;
+            field_start = false;
+            continue;
+        }
+        field_start = false;
+
+        if i >= line_end {
+            line_end = line_break(i);
+        }
+        if let Some(len) = delimiter.match_len(&content[i..line_end]) {
+            visit(Token::Delimiter(i..i + len));
+            field_start = true;
+            i += len;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Calls `visit` with the offset and value of every CR and LF outside quoted fields
+///
+/// As for [`structural::scan_unquoted`], only the LF ending a comment line is visited.
+pub fn scan_line_breaks(
+    content: &[u8],
+    delimiter: &Delimiter,
+    quoting: bool,
+    comment: Option<u8>,
+    mut visit: impl FnMut(usize, u8),
+) {
+    scan(content, delimiter, quoting, comment, |token| {
+        if let Token::LineBreak(offset) = token {
+            visit(offset, content[offset]);
+        }
+    });
+}
+
+/// Byte ranges of the fields of the first record in `raw`, quotes included
+///
+/// Empty lines and comment lines before the record are skipped; the ranges
+/// exclude the record terminator.
+pub fn field_spans(
+    raw: &[u8],
+    delimiter: &Delimiter,
+    quoting: bool,
+    comment: Option<u8>,
+) -> Vec<Range<usize>> {
+    let mut start = None;
+    scan_records(raw, delimiter, quoting, comment, |span| {
+        start.get_or_insert(span.start);
+    });
+    let start = start.unwrap_or(raw.len());
+
+    let mut spans = Vec::new();
+    let mut field_start = start;
+    let mut record_end = None;
+    scan(&raw[start..], delimiter, quoting, None, |token| {
+        if record_end.is_some() {
+            return;
+        }
+        match token {
+            Token::Delimiter(range) => {
+                spans.push(field_start..start + range.start);
+                field_start = start + range.end;
+            }
+            Token::LineBreak(offset) => record_end = Some(start + offset),
+        }
+    });
+    spans.push(field_start..record_end.unwrap_or(raw.len()));
+    spans
+}
+
+/// Calls `visit` with the byte range of every record, excluding its terminator
+///
+/// Empty lines and comment lines are skipped.
+pub fn scan_records(
+    content: &[u8],
+    delimiter: &Delimiter,
+    quoting: bool,
+    comment: Option<u8>,
+    mut visit: impl FnMut(Range<usize>),
+) {
+    let is_record =
+        |line: &Range<usize>| !line.is_empty() && !is_comment(content, line.start, comment);
+    let mut line_start = 0;
+    scan_line_breaks(content, delimiter, quoting, comment, |offset, _| {
+        if is_record(&(line_start..offset)) {
+            visit(line_start..offset);
+        }
+        line_start = offset + 1;
+    });
+    if is_record(&(line_start..content.len())) {
+        visit(line_start..content.len());
+    }
+}
+
+/// Calls `visit` with the byte range of every comment line, excluding its LF
+pub fn scan_comments(
+    content: &[u8],
+    delimiter: &Delimiter,
+    quoting: bool,
+    comment: u8,
+    mut visit: impl FnMut(Range<usize>),
+) {
+    let mut line_start = 0;
+    scan_line_breaks(content, delimiter, quoting, Some(comment), |offset, _| {
+        if is_comment(content, line_start, Some(comment)) {
+            visit(line_start..offset);
+        }
+        line_start = offset + 1;
+    });
+    if is_comment(content, line_start, Some(comment)) {
+        visit(line_start..content.len());
+    }
+}
+
+/// Whether a comment line starts at `start`, which must be the start of a record
+fn is_comment(content: &[u8], start: usize, comment: Option<u8>) -> bool {
+    comment.is_some() && content.get(start) == comment.as_ref()
+}
+
+/// Counts fields per record
+pub fn field_counts(
+    content: &[u8],
+    delimiter: &Delimiter,
+    quoting: bool,
+    comment: Option<u8>,
+) -> FieldCounts {
+    let mut counts = FieldCounts::default();
+    let mut record_start = 0;
+    let mut fields = 1;
+    scan(content, delimiter, quoting, comment, |token| match token {
+        Token::Delimiter(_) => fields += 1,
+        Token::LineBreak(offset) => {
+            if (offset > record_start || fields > 1) && !is_comment(content, record_start, comment)
+            {
+                counts.push(fields);
+            }
+            fields = 1;
+            record_start = offset + 1;
+        }
+    });
+    if (content.len() > record_start || fields > 1) && !is_comment(content, record_start, comment) {
+        counts.push(fields);
+    }
+    counts
+}
+
+/// The value of a field given its raw bytes, without quotes or escapes
+pub fn unquote(field: &[u8], quoting: bool) -> Cow<'_, [u8]> {
+    if !quoting || field.first() != Some(&b'"') {
+        return Cow::Borrowed(field);
+    }
+    let mut value = Vec::with_capacity(field.len());
+    let mut i = 1;
+    while i < field.len() {
+        if field[i] == b'"' {
+            if field.get(i + 1) == Some(&b'"') {
+                value.push(b'"');
+                i += 2;
+                continue;
+            }
+            // The closing quote; the rest of the field is kept as it is
+            value.extend_from_slice(&field[i + 1..]);
+            break;
+        }
+        value.push(field[i]);
+        i += 1;
+    }
+    Cow::Owned(value)
+}
+
+/// A record that isn't valid UTF-8, reported like the csv crate does
+#[derive(Debug, Clone, Error)]
+#[error("CSV parse error: record {record} (line {line}, field: {field}, byte: {byte}): {error}")]
+pub struct Utf8Error {
+    /// Index of the record, counting the header
+    pub record: usize,
+    /// Line the record starts on, counting from 1
+    pub line: usize,
+    /// Index of the invalid field
+    pub field: usize,
+    /// Offset of the record
+    pub byte: usize,
+    pub error: csv::Utf8Error,
+}
+
+/// Byte ranges of a record read by a [`Reader`]
+#[derive(Debug, Clone)]
+pub struct RecordSpans {
+    /// Index of the record, counting the header
+    pub index: usize,
+    /// The record, excluding its terminator
+    pub record: Range<usize>,
+    /// Each field, quotes included
+    pub fields: Vec<Range<usize>>,
+}
+
+/// Reads the records of content split by a [`Delimiter`], in order
+pub struct Reader<'a> {
+    content: &'a [u8],
+    delimiter: &'a Delimiter,
+    quoting: bool,
+    records: std::vec::IntoIter<Range<usize>>,
+    index: usize,
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(content: &'a [u8], delimiter: &'a Delimiter, options: &ValidationOptions) -> Self {
+        let quoting = !options.lazy_quotes;
+        let mut records = Vec::new();
+        scan_records(content, delimiter, quoting, options.comment, |span| {
+            records.push(span)
+        });
+        Reader {
+            content,
+            delimiter,
+            quoting,
+            records: records.into_iter(),
+            index: 0,
+            position: 0,
+        }
+    }
+
+    /// Reads the fields of the next record into `record`, returning its byte ranges
+    ///
+    /// Returns `None` at the end of the content.
+    pub fn read_byte_record(&mut self, record: &mut ByteRecord) -> Option<RecordSpans> {
+        let span = self.records.next()?;
+        let raw = &self.content[span.clone()];
+        let fields: Vec<Range<usize>> = field_spans(raw, self.delimiter, self.quoting, None)
+            .into_iter()
+            .map(|field| span.start + field.start..span.start + field.end)
+            .collect();
+        record.clear();
+        for field in &fields {
+            record.push_field(&unquote(&self.content[field.clone()], self.quoting));
+        }
+        let rest = &self.content[span.end..];
+        self.position = span.end
+            + if rest.starts_with(b"\r\n") {
+                2
+            } else {
+                usize::from(!rest.is_empty())
+            };
+        self.index += 1;
+        Some(RecordSpans {
+            index: self.index - 1,
+            record: span,
+            fields,
+        })
+    }
+
+    /// Offset just past the last record read, including its terminator
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Reads the next record into `record`, returning `false` at the end of the content
+    pub fn read_record(&mut self, record: &mut StringRecord) -> Result<bool, Utf8Error> {
+        let mut byte_record = ByteRecord::new();
+        let Some(spans) = self.read_byte_record(&mut byte_record) else {
+            return Ok(false);
+        };
+        *record = StringRecord::from_byte_record(byte_record)
+            .map_err(|e| self.utf8_error(&spans, e.utf8_error()))?;
+        Ok(true)
+    }
+
+    /// The error for the record with `spans` not being valid UTF-8
+    pub fn utf8_error(&self, spans: &RecordSpans, error: &csv::Utf8Error) -> Utf8Error {
+        let start = spans.record.start;
+        Utf8Error {
+            record: spans.index,
+            line: line_endings::line_number(self.content, start),
+            field: error.field(),
+            byte: start,
+            error: error.clone(),
+        }
+    }
+}
+
+/// Calls `visit` with the byte range of every record, split according to `options`
+///
+/// See [`structural::scan_records`].
+pub(crate) fn records(
+    content: &[u8],
+    options: &ValidationOptions,
+    visit: impl FnMut(Range<usize>),
+) {
+    let quoting = !options.lazy_quotes;
+    match &options.custom_delimiter {
+        Some(delimiter) => scan_records(content, delimiter, quoting, options.comment, visit),
+        None => {
+            structural::scan_records(content, options.delimiter, quoting, options.comment, visit)
+        }
+    }
+}
+
+/// Calls `visit` with the byte range of every comment line, split according to `options`
+///
+/// See [`structural::scan_comments`].
+pub(crate) fn comments(
+    content: &[u8],
+    options: &ValidationOptions,
+    comment: u8,
+    visit: impl FnMut(Range<usize>),
+) {
+    let quoting = !options.lazy_quotes;
+    match &options.custom_delimiter {
+        Some(delimiter) => scan_comments(content, delimiter, quoting, comment, visit),
+        None => structural::scan_comments(content, options.delimiter, quoting, comment, visit),
+    }
+}
+
+/// Calls `visit` with the offset and value of every CR and LF outside quoted fields
+///
+/// See [`structural::scan_unquoted`].
+pub(crate) fn line_breaks(
+    content: &[u8],
+    options: &ValidationOptions,
+    mut visit: impl FnMut(usize, u8),
+) {
+    let quoting = !options.lazy_quotes;
+    match &options.custom_delimiter {
+        Some(delimiter) => scan_line_breaks(content, delimiter, quoting, options.comment, visit),
+        None => structural::scan_unquoted(
+            content,
+            options.delimiter,
+            quoting,
+            options.comment,
+            |offset, byte| {
+                if byte == b'\r' || byte == b'\n' {
+                    visit(offset, byte);
+                }
+            },
+        ),
+    }
+}
+
+/// Byte ranges of the fields of the first record in `raw`, split according to `options`
+///
+/// See [`structural::field_spans`].
+pub(crate) fn fields(raw: &[u8], options: &ValidationOptions) -> Vec<Range<usize>> {
+    let quoting = !options.lazy_quotes;
+    match &options.custom_delimiter {
+        Some(delimiter) => field_spans(raw, delimiter, quoting, options.comment),
+        None => structural::field_spans(raw, options.delimiter, quoting, options.comment),
+    }
+}
+
+/// Counts fields per record, split according to `options`
+pub(crate) fn counts(content: &[u8], options: &ValidationOptions) -> FieldCounts {
+    let quoting = !options.lazy_quotes;
+    match &options.custom_delimiter {
+        Some(delimiter) => field_counts(content, delimiter, quoting, options.comment),
+        None => structural::field_counts(content, options.delimiter, quoting, options.comment),
+    }
+}
+
+/// Reads the first record of `content`, split according to `options`
+///
+/// Returns `None` when there is none or it can't be parsed.
+pub(crate) fn first_record(content: &[u8], options: &ValidationOptions) -> Option<StringRecord> {
+    let mut record = StringRecord::new();
+    let read = match &options.custom_delimiter {
+        Some(delimiter) => Reader::new(content, delimiter, options)
+            .read_record(&mut record)
+            .ok(),
+        None => csv_reader_builder(options)
+            .from_reader(content)
+            .read_record(&mut record)
+            .ok(),
+    };
+    (read == Some(true)).then_some(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(
+        content: &[u8],
+        delimiter: &Delimiter,
+        options: &ValidationOptions,
+    ) -> Vec<Vec<String>> {
+        let mut reader = Reader::new(content, delimiter, options);
+        let mut record = StringRecord::new();
+        let mut records = Vec::new();
+        while reader.read_record(&mut record).unwrap() {
+            records.push(record.iter().map(str::to_string).collect());
+        }
+        records
+    }
+
+    #[test]
+    fn test_literal_delimiters() {
+        let options = ValidationOptions::default();
+        let content = "a~|~\"b~|~\"\"c\"\"\"~|~d\r\n\r\n\"x\ny\"z~|~~|~|~\n1~|~2";
+        let records = read_all(
+            content.as_bytes(),
+            &Delimiter::Literal(b"~|~".to_vec()),
+            &options,
+        );
+        assert_eq!(
+            records,
+            vec![
+                vec!["a", "b~|~\"c\"", "d"],
+                vec!["x\nyz", "", "|~"],
+                vec!["1", "2"],
+            ]
+        );
+
+        let content = "a¦b¦\"c¦\"\nd¦é¦";
+        let delimiter = Delimiter::Literal("¦".as_bytes().to_vec());
+        assert_eq!(
+            read_all(content.as_bytes(), &delimiter, &options),
+            vec![vec!["a", "b", "c¦"], vec!["d", "é", ""]]
+        );
+        assert_eq!(
+            field_counts(content.as_bytes(), &delimiter, true, None),
+            field_counts(
+                b"a,b,c\nd,e,",
+                &Delimiter::Literal(b",".to_vec()),
+                true,
+                None
+            )
+        );
+
+        // A quote only opens a quoted field at the start of a field
+        let delimiter = Delimiter::Literal(b"||".to_vec());
+        assert_eq!(
+            field_spans(b"a\"||b\"||\"c||d", &delimiter, true, None),
+            vec![0..2, 4..6, 8..13]
+        );
+        assert_eq!(
+            field_spans(b"\"a||b\"||c", &delimiter, false, None),
+            vec![0..2, 4..6, 8..9]
+        );
+    }
+
+    #[test]
+    fn test_matches_csv_crate() {
+        // With a one-byte delimiter, records are read the same as by the csv crate
+        let samples: &[&[u8]] = &[
+            b"a,b\r\n\r\n\"c\"\"\",\"d\r\ne\"x\r\n,\n",
+            b"#c,\"\r\na,b\n#\n\"x,y\n",
+            b"\"a\"b\"c,d\r\"e",
+        ];
+        let delimiter = Delimiter::Literal(b",".to_vec());
+        for &content in samples {
+            for lazy_quotes in [false, true] {
+                let options = ValidationOptions {
+                    lazy_quotes,
+                    comment: Some(b'#'),
+                    ..Default::default()
+                };
+                let mut csv_reader = csv_reader_builder(&options).from_reader(content);
+                let expected: Vec<Vec<String>> = csv_reader
+                    .records()
+                    .map(|record| record.unwrap().iter().map(str::to_string).collect())
+                    .collect();
+                assert_eq!(read_all(content, &delimiter, &options), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pattern_delimiter() {
+        let options = ValidationOptions::default();
+        let delimiter = Delimiter::pattern(r"\s*;\s*").unwrap();
+        assert_eq!(
+            read_all(b"a ; b;\"c ; \" \n d  ;e", &delimiter, &options),
+            vec![vec!["a", "b", "c ;  "], vec![" d", "e"]]
+        );
+
+        // Empty matches don't split fields, and matches end at a line break
+        let delimiter = Delimiter::pattern(r"\s*").unwrap();
+        assert_eq!(
+            read_all(b"a b\n\nc", &delimiter, &options),
+            vec![vec!["a", "b"], vec!["c"]]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Duplicate row and duplicate key detection
+//!
+//! Rows (or key columns) are remembered by a 128-bit hash rather than by
+//! value, which keeps memory at a few dozen bytes per record. For files whose
+//! hashes don't fit in memory either, a Bloom filter uses a fixed amount of
+//! memory; it cannot tell where the first occurrence was, and a small share
+//! of what it reports are false positives.
+
+use crate::collector::ErrorCollector;
+use crate::{CsvError, CsvErrorKind, ValidationOptions};
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// How previously seen rows are remembered
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UniqueMode {
+    /// Exact, using a hash set of every row
+    #[default]
+    Exact,
+    /// Probabilistic, using a Bloom filter sized for the expected number of rows
+    Bloom {
+        expected_rows: usize,
+        false_positive_rate: f64,
+    },
+}
+
+/// Where the first occurrence of a duplicate was seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirstOccurrence {
+    Record(usize),
+    /// Not known in Bloom filter mode
+    Unknown,
+}
+
+impl fmt::Display for FirstOccurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FirstOccurrence::Record(record_num) => write!(f, "first seen in record {}", record_num),
+            FirstOccurrence::Unknown => write!(f, "possibly a Bloom filter false positive"),
+        }
+    }
+}
+
+/// The rows seen so far
+enum Seen {
+    Exact(HashMap<u128, usize>),
+    Bloom(BloomFilter),
+}
+
+impl Seen {
+    fn new(mode: UniqueMode) -> Self {
+        match mode {
+            UniqueMode::Exact => Seen::Exact(HashMap::new()),
+            UniqueMode::Bloom {
+                expected_rows,
+                false_positive_rate,
+            } => Seen::Bloom(BloomFilter::new(expected_rows, false_positive_rate)),
+        }
+    }
+
+    /// Remembers a row, returning its first occurrence if it was seen before
+    fn insert(&mut self, hash: u128, record_num: usize) -> Option<FirstOccurrence> {
+        match self {
+            Seen::Exact(seen) => match seen.get(&hash) {
+                Some(&first) => Some(FirstOccurrence::Record(first)),
+                None => {
+                    seen.insert(hash, record_num);
+                    None
+                }
+            },
+            Seen::Bloom(filter) => filter.insert(hash).then_some(FirstOccurrence::Unknown),
+        }
+    }
+}
+
+/// A Bloom filter over 128-bit hashes
+struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    fn new(expected_rows: usize, false_positive_rate: f64) -> Self {
+        let n = expected_rows.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-n * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0);
+        let hashes = ((bits / n) * ln2).round().clamp(1.0, 32.0) as u32;
+        BloomFilter {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes,
+        }
+    }
+
+    /// Word index and mask of each bit for `hash`, using double hashing
+    fn bits(&self, hash: u128) -> impl Iterator<Item = (usize, u64)> + use<> {
+        let len = self.bits.len() as u64 * 64;
+        let (h1, h2) = ((hash >> 64) as u64, hash as u64);
+        (0..self.hashes as u64).map(move |i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % len;
+            ((bit / 64) as usize, 1u64 << (bit % 64))
+        })
+    }
+
+    fn contains(&self, hash: u128) -> bool {
+        self.bits(hash)
+            .all(|(word, mask)| self.bits[word] & mask != 0)
+    }
+
+    /// Sets the bits of `hash`, returning whether they were all set already
+    fn insert(&mut self, hash: u128) -> bool {
+        let present = self.contains(hash);
+        for (word, mask) in self.bits(hash) {
+            self.bits[word] |= mask;
+        }
+        present
+    }
+}
+
+/// 128-bit hash of some fields, built from two independently seeded hashers
+pub(crate) fn hash_fields<'a>(fields: impl Iterator<Item = &'a str> + Clone) -> u128 {
+    let hash = |seed: u64| {
+        let mut hasher = DefaultHasher::new();
+        seed.hash(&mut hasher);
+        // Hashing each field with its length keeps ("a,b", "c") apart from ("a", "b,c")
+        fields.clone().for_each(|field| field.hash(&mut hasher));
+        hasher.finish()
+    };
+    ((hash(0) as u128) << 64) | hash(1) as u128
+}
+
+/// Indices of the named columns in `header`, or `None` if any is missing
+pub(crate) fn column_indices(
+    names: &[String],
+    header: Option<&StringRecord>,
+) -> Option<Vec<usize>> {
+    let header = header?;
+    names
+        .iter()
+        .map(|name| header.iter().position(|column| column == name))
+        .collect()
+}
+
+/// Checks records for duplicate rows and duplicate keys
+pub(crate) struct UniqueChecker {
+    rows: Option<Seen>,
+    key: Option<(Vec<usize>, Seen)>,
+}
+
+impl UniqueChecker {
+    /// Creates a checker for the rules enabled in `options`, if any
+    ///
+    /// Key columns are resolved against `header`; unknown columns, which
+    /// [`read_header`](crate::read_header) reports, disable the key check.
+    pub fn new(options: &ValidationOptions, header: Option<&StringRecord>) -> Option<Self> {
+        let rows = options.unique_rows.then(|| Seen::new(options.unique_mode));
+        let key = (!options.unique_key.is_empty())
+            .then(|| column_indices(&options.unique_key, header))
+            .flatten()
+            .map(|columns| (columns, Seen::new(options.unique_mode)));
+
+        (rows.is_some() || key.is_some()).then_some(UniqueChecker { rows, key })
+    }
+
+    pub fn check(&mut self, record: &StringRecord, record_num: usize, errors: &mut ErrorCollector) {
+        let to_vec = || record.iter().map(|s| s.to_string()).collect();
+
+        if let Some(rows) = &mut self.rows
+            && let Some(first) = rows.insert(hash_fields(record.iter()), record_num)
+        {
+            errors.push(CsvError {
+                record: Some(to_vec()),
+                record_num,
+                error: CsvErrorKind::DuplicateRow { first },
+            });
+        }
+
+        if let Some((columns, keys)) = &mut self.key {
+            // Records too short for the key are already reported as field count errors
+            let Some(values) = columns
+                .iter()
+                .map(|&i| record.get(i))
+                .collect::<Option<Vec<_>>>()
+            else {
+                return;
+            };
+            if let Some(first) = keys.insert(hash_fields(values.iter().copied()), record_num) {
+                errors.push(CsvError {
+                    record: Some(to_vec()),
+                    record_num,
+                    error: CsvErrorKind::DuplicateKey {
+                        key: format!("{:?}", values),
+                        first,
+                    },
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::new(10_000, 0.01);
+        let hashes: Vec<u128> = (0..10_000u32)
+            .map(|n| hash_fields([n.to_string().as_str()].into_iter()))
+            .collect();
+        hashes.iter().for_each(|&h| {
+            filter.insert(h);
+        });
+        assert!(hashes.iter().all(|&h| filter.contains(h)));
+
+        let false_positives = (10_000..20_000u32)
+            .filter(|n| filter.contains(hash_fields([n.to_string().as_str()].into_iter())))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_hash_fields_separates_fields() {
+        assert_ne!(
+            hash_fields(["a,b", "c"].into_iter()),
+            hash_fields(["a", "b,c"].into_iter())
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Filesystem notifications for `--watch`
+//!
+//! Files are watched through their parent directory, so that editors and
+//! exporters that replace a file by renaming a new one over it are noticed.
+//! Directories are watched recursively for CSV files.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How long the filesystem must be quiet before a batch of changes is reported
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Extensions of compressed files, stripped before looking for a CSV extension
+const COMPRESSED_EXTENSIONS: [&str; 4] = ["gz", "zst", "bz2", "xz"];
+
+/// Watches files and directories for changes
+pub struct FileWatcher {
+    // Kept alive for as long as events are wanted
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl FileWatcher {
+    /// Starts watching `paths`, which may be files or directories
+    pub fn new(paths: &[PathBuf]) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        for path in paths {
+            let path = std::path::absolute(path)?;
+            if path.is_dir() {
+                watcher.watch(&path, RecursiveMode::Recursive)?;
+            } else {
+                let parent = path.parent().unwrap_or(Path::new("/"));
+                watcher.watch(parent, RecursiveMode::NonRecursive)?;
+            }
+        }
+        Ok(FileWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Blocks until something changes, returning the absolute paths that
+    /// changed once the filesystem has settled
+    ///
+    /// Returns `None` when the watcher has stopped.
+    pub fn next_changes(&self) -> Option<notify::Result<BTreeSet<PathBuf>>> {
+        let mut changed = BTreeSet::new();
+        let mut event = self.events.recv().ok()?;
+        loop {
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    changed.extend(event.paths);
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            event = match self.events.recv_timeout(SETTLE_TIME) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) if !changed.is_empty() => {
+                    return Some(Ok(changed));
+                }
+                Err(RecvTimeoutError::Timeout) => self.events.recv().ok()?,
+                Err(RecvTimeoutError::Disconnected) => return Some(Ok(changed)),
+            };
+        }
+    }
+}
+
+/// Whether `path` names a CSV or TSV file, possibly compressed
+pub fn is_csv_file(path: &Path) -> bool {
+    let mut path = path.to_path_buf();
+    if path
+        .extension()
+        .is_some_and(|ext| COMPRESSED_EXTENSIONS.iter().any(|c| ext == *c))
+    {
+        path.set_extension("");
+    }
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv") || ext.eq_ignore_ascii_case("tsv"))
+}
+
+/// The CSV files in a directory and its subdirectories, sorted
+pub fn csv_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_csv_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_files() {
+        assert!(is_csv_file(Path::new("data.csv")));
+        assert!(is_csv_file(Path::new("dir/DATA.TSV.gz")));
+        assert!(!is_csv_file(Path::new("data.gz")));
+        assert!(!is_csv_file(Path::new("notes.txt")));
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        for name in ["b.csv", "a.txt", "nested/c.csv.gz"] {
+            std::fs::write(dir.path().join(name), "a,b\n").unwrap();
+        }
+        assert_eq!(
+            csv_files(dir.path()).unwrap(),
+            vec![dir.path().join("b.csv"), dir.path().join("nested/c.csv.gz")]
+        );
+    }
+
+    #[test]
+    fn test_watcher_reports_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        std::fs::write(&path, "a,b\n").unwrap();
+
+        let watcher = FileWatcher::new(std::slice::from_ref(&path)).unwrap();
+        std::fs::write(&path, "a,b\n1,2\n").unwrap();
+        let changed = watcher.next_changes().unwrap().unwrap();
+        assert!(changed.contains(&std::path::absolute(&path).unwrap()));
+    }
+}
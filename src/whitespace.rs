@@ -0,0 +1,205 @@
+//! Whitespace hygiene rules for fields
+//!
+//! Values such as `" foo"` or `"bar  "` parse fine but break joins downstream.
+//! Whether a field was quoted is not kept by the csv crate, so it is read
+//! from the raw bytes of the record.
+
+use crate::collector::ErrorCollector;
+use crate::tokenizer::Delimiter;
+use crate::{Column, CsvError, CsvErrorKind, ValidationOptions, structural};
+use csv::StringRecord;
+
+/// Which fields are checked for leading and trailing whitespace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldScope {
+    /// No fields
+    #[default]
+    None,
+    /// Only fields that are not quoted
+    Unquoted,
+    /// Only quoted fields
+    Quoted,
+    /// All fields
+    All,
+}
+
+impl FieldScope {
+    pub(crate) fn includes(self, quoted: bool) -> bool {
+        match self {
+            FieldScope::None => false,
+            FieldScope::Unquoted => !quoted,
+            FieldScope::Quoted => quoted,
+            FieldScope::All => true,
+        }
+    }
+}
+
+/// Whether any whitespace rule is enabled in `options`
+pub(crate) fn enabled(options: &ValidationOptions) -> bool {
+    options.surrounding_whitespace != FieldScope::None
+        || options.non_breaking_spaces
+        || (options.tabs && !tab_is_delimiter(options))
+}
+
+/// Whether fields are split on a single tab, so that they can't contain one
+///
+/// A custom delimiter replaces `options.delimiter`; tabs in fields split on a
+/// pattern or a longer literal are still reported.
+fn tab_is_delimiter(options: &ValidationOptions) -> bool {
+    match &options.custom_delimiter {
+        Some(Delimiter::Literal(bytes)) => bytes == b"\t",
+        Some(Delimiter::Pattern(_)) => false,
+        None => options.delimiter == b'\t',
+    }
+}
+
+/// Checks the fields of one record against the whitespace rules
+///
+/// `quoted` tells which fields of the record were quoted.
+pub(crate) fn validate_whitespace(
+    quoted: &[bool],
+    record: &StringRecord,
+    header: Option<&StringRecord>,
+    record_num: usize,
+    options: &ValidationOptions,
+    errors: &mut ErrorCollector,
+) {
+    let mut push = |error: CsvErrorKind| {
+        errors.push(CsvError {
+            record: Some(record.iter().map(|s| s.to_string()).collect()),
+            record_num,
+            error,
+        });
+    };
+
+    let tabs = options.tabs && !tab_is_delimiter(options);
+    for (i, field) in record.iter().enumerate() {
+        let column = || Column::new(i, header);
+
+        if options
+            .surrounding_whitespace
+            .includes(quoted.get(i).copied().unwrap_or(false))
+        {
+            if field.starts_with(char::is_whitespace) {
+                push(CsvErrorKind::LeadingWhitespace(column()));
+            }
+            if field.ends_with(char::is_whitespace) {
+                push(CsvErrorKind::TrailingWhitespace(column()));
+            }
+        }
+        if options.non_breaking_spaces && field.contains(['\u{a0}', '\u{2007}', '\u{202f}']) {
+            push(CsvErrorKind::NonBreakingSpace(column()));
+        }
+        if tabs && field.contains('\t') {
+            push(CsvErrorKind::Tab(column()));
+        }
+    }
+}
+
+/// Whether each field of the raw record is quoted
+///
+/// `raw` is the record as it appears in the file, possibly preceded by the
+/// line breaks of skipped empty lines.
+pub(crate) fn quoted_fields(
+    raw: &[u8],
+    delimiter: u8,
+    quoting: bool,
+    comment: Option<u8>,
+) -> Vec<bool> {
+    structural::field_spans(raw, delimiter, quoting, comment)
+        .into_iter()
+        .map(|span| quoting && raw.get(span.start) == Some(&b'"'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorLimits;
+
+    #[test]
+    fn test_quoted_fields() {
+        assert_eq!(
+            quoted_fields(b"\r\n\"a,b\",c,\"d\"\"\"\r\nx", b',', true, None),
+            vec![true, false, true]
+        );
+        assert_eq!(
+            quoted_fields(b"\"a\",b", b',', false, None),
+            vec![false, false]
+        );
+    }
+
+    #[test]
+    fn test_whitespace_rules() {
+        let raw = b" a,\"b \",c\td,e\xc2\xa0f\n";
+        let record = StringRecord::from(vec![" a", "b ", "c\td", "e\u{a0}f"]);
+        let header = StringRecord::from(vec!["w", "x", "y", "z"]);
+
+        let check = |options: &ValidationOptions| {
+            let limits = ErrorLimits::default();
+            let mut errors = ErrorCollector::new(&limits);
+            let quoted = quoted_fields(raw, b',', true, None);
+            validate_whitespace(&quoted, &record, Some(&header), 1, options, &mut errors);
+            let (errors, _) = errors.into_parts();
+            errors.into_iter().map(|e| e.error).collect::<Vec<_>>()
+        };
+        let column = |index: usize, name: &str| Column {
+            index,
+            name: Some(name.to_string()),
+        };
+
+        let unquoted = ValidationOptions {
+            surrounding_whitespace: FieldScope::Unquoted,
+            ..Default::default()
+        };
+        assert_eq!(
+            check(&unquoted),
+            vec![CsvErrorKind::LeadingWhitespace(column(1, "w"))]
+        );
+
+        let quoted = ValidationOptions {
+            surrounding_whitespace: FieldScope::Quoted,
+            ..Default::default()
+        };
+        assert_eq!(
+            check(&quoted),
+            vec![CsvErrorKind::TrailingWhitespace(column(2, "x"))]
+        );
+
+        let stray = ValidationOptions {
+            non_breaking_spaces: true,
+            tabs: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            check(&stray),
+            vec![
+                CsvErrorKind::Tab(column(3, "y")),
+                CsvErrorKind::NonBreakingSpace(column(4, "z")),
+            ]
+        );
+    }
+    #[test]
+    fn test_tab_delimiter() {
+        let record = StringRecord::from(vec!["a\tb", "c"]);
+        let check = |delimiter: u8, custom_delimiter: Option<Delimiter>| {
+            let options = ValidationOptions {
+                delimiter,
+                custom_delimiter,
+                tabs: true,
+                ..Default::default()
+            };
+            let limits = ErrorLimits::default();
+            let mut errors = ErrorCollector::new(&limits);
+            validate_whitespace(&[], &record, None, 1, &options, &mut errors);
+            enabled(&options) && !errors.into_parts().0.is_empty()
+        };
+
+        assert!(check(b',', None));
+        assert!(!check(b'\t', None));
+        // The custom delimiter is the one fields are split on
+        assert!(check(b'\t', Some(Delimiter::Literal(b"||".to_vec()))));
+        assert!(!check(b',', Some(Delimiter::Literal(b"\t".to_vec()))));
+        assert!(check(b',', Some(Delimiter::pattern(r"\s*;\s*").unwrap())));
+    }
+}